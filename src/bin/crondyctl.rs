@@ -0,0 +1,77 @@
+use std::io::{Read, Write};
+use std::os::unix::net::UnixStream;
+use std::process;
+
+use crondy::paths;
+
+/*
+ * crondyctl [-s SOCKET] COMMAND [ENTRY]
+ *
+ * Sends COMMAND to a running crondy over its control socket and prints the
+ * answer. See control.rs for the commands and the protocol.
+ */
+
+const USAGE: &str = "usage: crondyctl [-s SOCKET] COMMAND [ENTRY]
+
+commands:
+    entries          list entries and when they are next due
+    jobs             list running jobs
+    run ENTRY        run ENTRY now
+    pause [ENTRY]    skip scheduled runs of ENTRY, or of everything
+    resume [ENTRY]   undo pause
+    reload           read the crontabs again
+
+ENTRY is FILE:LINE, or just LINE if only one crontab has that line.
+SOCKET is where crondy listens, as crondy has it: $CRONDY_SOCKET, or
+/run/crondy.sock for root and $XDG_RUNTIME_DIR/crondy.sock for anyone else.";
+
+fn main() {
+    let mut args: Vec<String> = std::env::args().skip(1).collect();
+    let env = |name: &str| std::env::var(name).ok();
+    let euid = unsafe { libc::geteuid() };
+    let mut socket = env("CRONDY_SOCKET").or_else(|| paths::socket(euid, &env));
+    if args.first().map(String::as_str) == Some("-s") {
+        if args.len() < 2 {
+            fail(USAGE);
+        }
+        socket = Some(args.remove(1));
+        args.remove(0);
+    }
+    match args.first().map(String::as_str) {
+        None | Some("-h") | Some("--help") | Some("help") => {
+            println!("{}", USAGE);
+            return;
+        }
+        _ => (),
+    }
+
+    let socket = socket.unwrap_or_else(|| {
+        fail("don't know where crondy's socket is, give it with -s or CRONDY_SOCKET")
+    });
+    let mut stream = UnixStream::connect(&socket)
+        .unwrap_or_else(|error| fail(&format!("could not connect to {}: {}", socket, error)));
+    let request = format!("{}\n", args.join(" "));
+    let mut reply = String::new();
+    if let Err(error) = stream
+        .write_all(request.as_bytes())
+        .and_then(|_| stream.read_to_string(&mut reply))
+    {
+        fail(&format!("could not talk to crondy: {}", error));
+    }
+
+    let mut lines = reply.lines();
+    match lines.next() {
+        Some("ok") => {
+            for line in lines {
+                println!("{}", line);
+            }
+        }
+        Some(status) if status.starts_with("error ") => fail(&status["error ".len()..]),
+        _ => fail("crondy hung up without an answer"),
+    }
+}
+
+fn fail(message: &str) -> ! {
+    eprintln!("crondyctl: {}", message);
+    process::exit(1);
+}
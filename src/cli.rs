@@ -0,0 +1,325 @@
+use std::path::Path;
+use std::time::Duration;
+
+use crondy::{options, paths};
+use failure::{bail, format_err, Error};
+
+use crate::executor::Limits;
+use crate::logging::Backend;
+
+pub const USAGE: &str = "\
+usage: crondy [OPTIONS]
+       crondy [OPTIONS] history [--entry LINE|TEXT] [--since TIME] [--until TIME]
+                                [--outcome OUTCOME]
+       crondy [OPTIONS] next [--count N] [--within DURATION | --until TIME] [--from TIME]
+                             [--timezone TZ] [--json] [CRONTAB]
+       crondy [OPTIONS] check [--system] [CRONTAB...]
+       crondy [OPTIONS] simulate --from TIME --to TIME [--timezone TZ]
+                                 [--jump [+|-]DURATION@TIME]... [CRONTAB]
+
+Without a command, runs the jobs in the crontabs until stopped.
+
+options:
+    -c, --crontab PATH          a crontab, or a directory of them; may be repeated
+                                [$CRONTAB or /etc/crontab]
+    -d, --daemon                go into the background once started up; log with
+                                --log-format syslog to see what happens after that
+    -f, --foreground            stay in the foreground (the default)
+    --log-format FORMAT         pretty, json, syslog or syslog:SOCKET
+                                [$CRONDY_LOG_FORMAT or pretty]
+    --log-level FILTERS         error, warn, info, debug or trace, for everything or
+                                per module as in info,crondy::executor=debug
+                                [$CRONDY_LOG or error; info for json, syslog]
+    --pidfile PATH              [$CRONDY_PIDFILE, or /run/crondy.pid for root and
+                                $XDG_RUNTIME_DIR/crondy.pid for anyone else]
+    --state-dir DIR             [$CRONDY_STATE_DIR, or /var/lib/crondy for root and
+                                $XDG_STATE_HOME/crondy for anyone else]
+    --socket PATH               the control socket [$CRONDY_SOCKET, or
+                                /run/crondy.sock for root and
+                                $XDG_RUNTIME_DIR/crondy.sock for anyone else]
+    --max-jobs N                [$CRONDY_MAX_JOBS]
+    --metrics-file PATH         for node_exporter [$CRONDY_METRICS_FILE]
+    --metrics-address ADDRESS   a loopback address to serve metrics on
+                                [$CRONDY_METRICS_ADDRESS]
+    --shutdown-timeout DURATION how long jobs get to finish when stopping
+                                [$CRONDY_SHUTDOWN_TIMEOUT or 30s]
+    --forward-signals           pass SIGTERM and SIGINT on to jobs when stopping
+                                [$CRONDY_FORWARD_SIGNALS]
+    -h, --help                  show this and exit
+";
+
+/// How crondy is set up, from the command line or else the environment.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Config {
+    pub crontabs: Vec<String>,
+    pub daemon: bool,
+    pub log_format: Backend,
+    pub log_filters: Option<String>,
+    pub pidfile: Option<String>,
+    pub state_dir: Option<String>,
+    pub socket: Option<String>,
+    pub limits: Limits,
+    pub metrics_file: Option<String>,
+    pub metrics_address: Option<String>,
+    pub shutdown_timeout: Duration,
+    pub forward_signals: bool,
+}
+
+/// What crondy was asked to do.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Action {
+    Run,
+    Help,
+    History(Vec<String>),
+    Next(Vec<String>),
+    Check(Vec<String>),
+    Simulate(Vec<String>),
+}
+
+impl Config {
+    /// The settings from environment variables, which `env` looks up.
+    pub fn from_env(env: &dyn Fn(&str) -> Option<String>) -> Result<Self, Error> {
+        let limit = |name: &str| env(name).map(|value| positive(name, &value)).transpose();
+        let euid = unsafe { libc::geteuid() };
+        Ok(Config {
+            crontabs: vec![env("CRONTAB").unwrap_or("/etc/crontab".to_owned())],
+            daemon: false,
+            log_format: match env("CRONDY_LOG_FORMAT") {
+                Some(format) => Backend::parse(&format)?,
+                None => Backend::Pretty,
+            },
+            log_filters: env("CRONDY_LOG"),
+            pidfile: env("CRONDY_PIDFILE").or_else(|| paths::pidfile(euid, env)),
+            state_dir: env("CRONDY_STATE_DIR").or_else(|| paths::state_dir(euid, env)),
+            socket: env("CRONDY_SOCKET").or_else(|| paths::socket(euid, env)),
+            limits: Limits {
+                max_jobs: limit("CRONDY_MAX_JOBS")?,
+            },
+            metrics_file: env("CRONDY_METRICS_FILE"),
+            metrics_address: env("CRONDY_METRICS_ADDRESS"),
+            shutdown_timeout: match env("CRONDY_SHUTDOWN_TIMEOUT") {
+                Some(value) => options::parse_duration("CRONDY_SHUTDOWN_TIMEOUT", &value)?,
+                None => Duration::from_secs(30),
+            },
+            forward_signals: match env("CRONDY_FORWARD_SIGNALS") {
+                Some(value) => options::parse_flag("CRONDY_FORWARD_SIGNALS", &value)?,
+                None => false,
+            },
+        })
+    }
+
+    /// Make the paths relative to `cwd` absolute, for a daemon that is
+    /// about to leave it.
+    pub fn make_absolute(&mut self, cwd: &Path) {
+        let absolute = |path: &mut String| {
+            if Path::new(path.as_str()).is_relative() {
+                *path = cwd.join(path.as_str()).to_string_lossy().into_owned();
+            }
+        };
+        self.crontabs.iter_mut().for_each(absolute);
+        self.pidfile.iter_mut().for_each(absolute);
+        self.state_dir.iter_mut().for_each(absolute);
+        self.socket.iter_mut().for_each(absolute);
+        self.metrics_file.iter_mut().for_each(absolute);
+        if let Backend::Syslog(socket) = &mut self.log_format {
+            *socket = cwd.join(&socket);
+        }
+    }
+}
+
+/* What commands read from the environment, unlike the daemon. */
+const COMMAND_ENV: &[&str] =
+    &["CRONTAB", "CRONDY_LOG", "CRONDY_STATE_DIR", "XDG_STATE_HOME", "HOME"];
+
+/*
+ * Options come before the command, if any; everything after the command is
+ * left for it to make sense of. Commands only read the few variables they
+ * need, so that a bad setting for the daemon can't get in the way of
+ * `crondy --help` or `crondy check`.
+ */
+pub fn parse(
+    args: &[String],
+    env: &dyn Fn(&str) -> Option<String>,
+) -> Result<(Config, Action), Error> {
+    let mut given = Vec::new();
+    let mut action = Action::Run;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let rest = args.as_slice().to_vec();
+        let wants_help = rest.iter().any(|arg| arg == "-h" || arg == "--help");
+        let command = match arg.as_str() {
+            "help" | "-h" | "--help" => Some(Action::Help),
+            "history" | "next" | "check" | "simulate" if wants_help => Some(Action::Help),
+            "history" => Some(Action::History(rest)),
+            "next" => Some(Action::Next(rest)),
+            "check" => Some(Action::Check(rest)),
+            "simulate" => Some(Action::Simulate(rest)),
+            _ => None,
+        };
+        if let Some(command) = command {
+            action = command;
+            break;
+        }
+
+        let (name, inline) = match arg.find('=') {
+            Some(at) if arg.starts_with("--") => (&arg[..at], Some(arg[at + 1..].to_owned())),
+            _ => (arg.as_str(), None),
+        };
+        if !name.starts_with('-') {
+            bail!("unknown command {:?}", arg);
+        }
+        match name {
+            "-d" | "--daemon" | "-f" | "--foreground" | "--forward-signals"
+                if inline.is_some() =>
+            {
+                bail!("{} takes no value", name)
+            }
+            "-d" | "--daemon" | "-f" | "--foreground" | "--forward-signals" => {
+                given.push((name, String::new()));
+                continue;
+            }
+            "-c" | "--crontab" | "--log-format" | "--log-level" | "--pidfile" | "--state-dir"
+            | "--socket" | "--max-jobs" | "--metrics-file" | "--metrics-address"
+            | "--shutdown-timeout" => (),
+            _ => bail!("unknown option {:?}", name),
+        }
+        match inline.or_else(|| args.next().cloned()) {
+            Some(value) => given.push((name, value)),
+            None => bail!("{} needs a value", name),
+        }
+    }
+
+    let mut config = match action {
+        Action::Run => Config::from_env(env)?,
+        _ => Config::from_env(&|name| env(name).filter(|_| COMMAND_ENV.contains(&name)))?,
+    };
+    let mut crontabs = Vec::new();
+    for (name, value) in given {
+        match name {
+            "-d" | "--daemon" => config.daemon = true,
+            "-f" | "--foreground" => config.daemon = false,
+            "--forward-signals" => config.forward_signals = true,
+            "-c" | "--crontab" => crontabs.push(value),
+            "--log-format" => config.log_format = Backend::parse(&value)?,
+            "--log-level" => config.log_filters = Some(value),
+            "--pidfile" => config.pidfile = Some(value),
+            "--state-dir" => config.state_dir = Some(value),
+            "--socket" => config.socket = Some(value),
+            "--max-jobs" => config.limits.max_jobs = Some(positive(name, &value)?),
+            "--metrics-file" => config.metrics_file = Some(value),
+            "--metrics-address" => config.metrics_address = Some(value),
+            "--shutdown-timeout" => {
+                config.shutdown_timeout = options::parse_duration(name, &value)?
+            }
+            _ => unreachable!(),
+        }
+    }
+    if !crontabs.is_empty() {
+        config.crontabs = crontabs;
+    }
+    Ok((config, action))
+}
+
+fn positive(name: &str, value: &str) -> Result<usize, Error> {
+    match value.parse() {
+        Ok(0) | Err(_) => Err(format_err!("{} must be a positive number (got {})", name, value)),
+        Ok(number) => Ok(number),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn parse_with(args: &[&str], env: &[(&str, &str)]) -> Result<(Config, Action), Error> {
+        let args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+        let env = |name: &str| {
+            env.iter()
+                .find(|(key, _)| *key == name)
+                .map(|(_, value)| value.to_string())
+        };
+        parse(&args, &env)
+    }
+
+    #[test]
+    fn defaults_to_running_etc_crontab() {
+        let (config, action) = parse_with(&[], &[]).unwrap();
+        assert_eq!(action, Action::Run);
+        assert_eq!(config.crontabs, vec!["/etc/crontab"]);
+        assert_eq!(config.log_format, Backend::Pretty);
+        assert_eq!(config.limits, Limits::default());
+        assert_eq!(config.shutdown_timeout, Duration::from_secs(30));
+        assert!(!config.daemon);
+    }
+
+    #[test]
+    fn makes_paths_absolute_for_daemons() {
+        let args = ["-d", "-c", "tab", "-c", "/etc/cron.d", "--log-format", "syslog:log"];
+        let env = [("CRONDY_STATE_DIR", "state"), ("CRONDY_PIDFILE", "crondy.pid")];
+        let (mut config, _) = parse_with(&args, &env).unwrap();
+        assert!(config.daemon);
+        config.make_absolute(Path::new("/home/me"));
+        assert_eq!(config.crontabs, vec!["/home/me/tab", "/etc/cron.d"]);
+        assert_eq!(config.state_dir.as_deref(), Some("/home/me/state"));
+        assert_eq!(config.pidfile.as_deref(), Some("/home/me/crondy.pid"));
+        assert_eq!(config.log_format, Backend::Syslog(PathBuf::from("/home/me/log")));
+        assert!(!parse_with(&["--daemon", "--foreground"], &[]).unwrap().0.daemon);
+    }
+
+    #[test]
+    fn prefers_options_to_the_environment() {
+        let env = [("CRONTAB", "/env/crontab"), ("CRONDY_MAX_JOBS", "2"), ("CRONDY_LOG", "debug")];
+        let (config, _) = parse_with(&["--max-jobs=3", "-c", "a", "--crontab", "b"], &env).unwrap();
+        assert_eq!(config.crontabs, vec!["a", "b"]);
+        assert_eq!(config.limits.max_jobs, Some(3));
+        assert_eq!(config.log_filters.as_deref(), Some("debug"));
+
+        let (config, _) = parse_with(
+            &["--log-format", "syslog:/tmp/log", "--log-level", "info", "--forward-signals"],
+            &env,
+        )
+        .unwrap();
+        assert_eq!(config.crontabs, vec!["/env/crontab"]);
+        assert_eq!(config.log_format, Backend::Syslog(PathBuf::from("/tmp/log")));
+        assert_eq!(config.log_filters.as_deref(), Some("info"));
+        assert!(config.forward_signals);
+    }
+
+    #[test]
+    fn hands_the_rest_to_commands() {
+        let (config, action) =
+            parse_with(&["--state-dir", "/tmp", "history", "--since", "2018-10-15"], &[]).unwrap();
+        assert_eq!(config.state_dir.as_deref(), Some("/tmp"));
+        assert_eq!(action, Action::History(vec!["--since".to_owned(), "2018-10-15".to_owned()]));
+        assert_eq!(parse_with(&["check"], &[]).unwrap().1, Action::Check(vec![]));
+        assert_eq!(parse_with(&["next", "--help"], &[]).unwrap().1, Action::Help);
+        assert_eq!(parse_with(&["-f", "--help"], &[]).unwrap().1, Action::Help);
+    }
+
+    #[test]
+    fn rejects_bad_command_lines() {
+        let error = parse_with(&["--frob"], &[]).unwrap_err().to_string();
+        assert_eq!(error, "unknown option \"--frob\"");
+        assert!(parse_with(&["frob"], &[]).is_err());
+        assert!(parse_with(&["--crontab"], &[]).is_err());
+        assert!(parse_with(&["--foreground=yes"], &[]).is_err());
+        assert!(parse_with(&["--max-jobs", "0"], &[]).is_err());
+        assert!(parse_with(&["--max-jobs-per-user", "2"], &[]).is_err());
+        assert!(parse_with(&["--log-format", "xml"], &[]).is_err());
+        assert!(parse_with(&[], &[("CRONDY_SHUTDOWN_TIMEOUT", "soon")]).is_err());
+    }
+
+    #[test]
+    fn leaves_the_daemon_settings_to_the_daemon() {
+        let env = [("CRONDY_LOG_FORMAT", "xml"), ("CRONTAB", "/env/crontab")];
+        assert!(parse_with(&[], &env).is_err());
+        assert_eq!(parse_with(&["--help"], &env).unwrap().1, Action::Help);
+        let (config, action) = parse_with(&["next"], &env).unwrap();
+        assert_eq!(action, Action::Next(vec![]));
+        assert_eq!(config.crontabs, vec!["/env/crontab"]);
+        assert_eq!(config.log_format, Backend::Pretty);
+        assert!(parse_with(&["check"], &[("CRONDY_MAX_JOBS", "many")]).is_ok());
+        assert!(parse_with(&["--log-format", "xml", "check"], &[]).is_err());
+    }
+}
@@ -0,0 +1,223 @@
+use std::fmt;
+use std::time::SystemTime;
+
+/*
+ * crondy counts time in minutes since the epoch, measured on the local wall
+ * clock: UTC shifted by the current offset from UTC. A DST change therefore
+ * looks to the scheduler like the clock jumping, which is how it gets
+ * handled (see the main loop).
+ */
+pub type Minute = i64;
+
+/// One minute of wall-clock time, broken down the way crontab fields see it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Moment {
+    pub year: i64,
+    pub month: usize,
+    pub day_of_month: usize,
+    pub day_of_week: usize, // 0 is Sunday
+    pub hour: usize,
+    pub minute: usize,
+}
+
+impl Moment {
+    pub fn at(minute: Minute) -> Self {
+        let days = minute.div_euclid(24 * 60);
+        let of_day = minute.rem_euclid(24 * 60) as usize;
+        let (year, month, day_of_month) = civil_from_days(days);
+        Moment {
+            year,
+            month,
+            day_of_month,
+            day_of_week: (days + 4).rem_euclid(7) as usize, // 1970-01-01 was a Thursday
+            hour: of_day / 60,
+            minute: of_day % 60,
+        }
+    }
+}
+
+impl fmt::Display for Moment {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{:04}-{:02}-{:02}T{:02}:{:02}",
+            self.year, self.month, self.day_of_month, self.hour, self.minute
+        )
+    }
+}
+
+/// Format seconds on crondy's clock (see `now_seconds`) like
+/// `2018-10-15T03:00:59`.
+pub fn format_seconds(seconds: i64) -> String {
+    format!(
+        "{}:{:02}",
+        Moment::at(seconds.div_euclid(60)),
+        seconds.rem_euclid(60)
+    )
+}
+
+/*
+ * Reads `YYYY-MM-DD`, `YYYY-MM-DDTHH:MM` or `YYYY-MM-DDTHH:MM:SS` on the
+ * local wall clock, into seconds on crondy's clock.
+ */
+pub fn parse_seconds(text: &str) -> Option<i64> {
+    let (date, time) = match text.find('T') {
+        Some(at) => (&text[..at], &text[at + 1..]),
+        None => (text, "00:00"),
+    };
+    let date: Vec<&str> = date.split('-').collect();
+    let time: Vec<&str> = time.split(':').collect();
+    if date.len() != 3 || time.len() < 2 || time.len() > 3 {
+        return None;
+    }
+    let number = |part: &str, low: i64, high: i64| {
+        part.parse::<i64>()
+            .ok()
+            .filter(|n| *n >= low && *n <= high && !part.starts_with('+'))
+    };
+    let year = number(date[0], 0, 9999)?;
+    let month = number(date[1], 1, 12)?;
+    let day = number(date[2], 1, 31)?;
+    let hour = number(time[0], 0, 23)?;
+    let minute = number(time[1], 0, 59)?;
+    let second = match time.get(2) {
+        Some(second) => number(second, 0, 59)?,
+        None => 0,
+    };
+    let days = days_from_civil(year, month, day);
+    if civil_from_days(days) != (year, month as usize, day as usize) {
+        return None; // e.g. the 30th of February
+    }
+    Some(((days * 24 + hour) * 60 + minute) * 60 + second)
+}
+
+pub fn now() -> Minute {
+    now_seconds().div_euclid(60)
+}
+
+/// Like `now`, but in seconds.
+pub fn now_seconds() -> i64 {
+    let seconds = match SystemTime::now().duration_since(SystemTime::UNIX_EPOCH) {
+        Ok(since) => since.as_secs() as i64,
+        Err(before) => -(before.duration().as_secs() as i64),
+    };
+    wall_clock(seconds)
+}
+
+/// Seconds since the epoch in UTC, on crondy's clock.
+pub fn wall_clock(utc_seconds: i64) -> i64 {
+    utc_seconds + utc_offset(utc_seconds)
+}
+
+fn utc_offset(seconds: i64) -> i64 {
+    let time = seconds as libc::time_t;
+    unsafe {
+        let mut tm: libc::tm = std::mem::zeroed();
+        if libc::localtime_r(&time, &mut tm).is_null() {
+            0
+        } else {
+            tm.tm_gmtoff as i64
+        }
+    }
+}
+
+/*
+ * Howard Hinnant's days_from_civil: turns a proleptic Gregorian date into
+ * days since 1970-01-01.
+ */
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year.rem_euclid(400);
+    let day_of_year = (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+/*
+ * Howard Hinnant's days_from_civil inverse: turns days since 1970-01-01 into
+ * a proleptic Gregorian (year, month, day).
+ */
+fn civil_from_days(days: i64) -> (i64, usize, usize) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * shifted_month + 2) / 5 + 1) as usize;
+    let month = if shifted_month < 10 {
+        shifted_month + 3
+    } else {
+        shifted_month - 9
+    } as usize;
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn moment_at_epoch() {
+        assert_eq!(
+            Moment::at(0),
+            Moment {
+                year: 1970,
+                month: 1,
+                day_of_month: 1,
+                day_of_week: 4,
+                hour: 0,
+                minute: 0
+            }
+        )
+    }
+
+    #[test]
+    fn moment_in_a_leap_year() {
+        // 2024-02-29T13:37
+        assert_eq!(
+            Moment::at(28_486_897),
+            Moment {
+                year: 2024,
+                month: 2,
+                day_of_month: 29,
+                day_of_week: 4,
+                hour: 13,
+                minute: 37
+            }
+        )
+    }
+
+    #[test]
+    fn formats_moments() {
+        assert_eq!(Moment::at(28_486_897).to_string(), "2024-02-29T13:37");
+        assert_eq!(format_seconds(28_486_897 * 60 + 5), "2024-02-29T13:37:05");
+    }
+
+    #[test]
+    fn parses_times() {
+        assert_eq!(parse_seconds("2024-02-29T13:37"), Some(28_486_897 * 60));
+        assert_eq!(parse_seconds("2024-02-29T13:37:05"), Some(28_486_897 * 60 + 5));
+        assert_eq!(parse_seconds("1970-01-02"), Some(24 * 60 * 60));
+        assert_eq!(parse_seconds("1969-12-31T23:59"), Some(-60));
+    }
+
+    #[test]
+    fn refuses_bad_times() {
+        assert_eq!(parse_seconds("2023-02-29"), None);
+        assert_eq!(parse_seconds("2024-13-01"), None);
+        assert_eq!(parse_seconds("2024-01-01T24:00"), None);
+        assert_eq!(parse_seconds("yesterday"), None);
+        assert_eq!(parse_seconds("2024-01-01T10"), None);
+    }
+
+    #[test]
+    fn moment_before_epoch() {
+        let moment = Moment::at(-1);
+        assert_eq!((moment.year, moment.month, moment.day_of_month), (1969, 12, 31));
+        assert_eq!((moment.hour, moment.minute, moment.day_of_week), (23, 59, 3));
+    }
+}
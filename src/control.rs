@@ -0,0 +1,340 @@
+use std::collections::HashSet;
+use std::fs;
+use std::io::{self, BufRead, BufReader, Write};
+use std::os::unix::fs::FileTypeExt;
+use std::os::unix::io::AsRawFd;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crondy::crontab::Entry;
+use failure::{bail, format_err, Error};
+use log::{debug, warn};
+
+/*
+ * The control protocol is one request per connection: the client sends a
+ * single line with a command, and crondy answers with `ok` or
+ * `error <message>` on the first line, followed by whatever the command
+ * has to say, and hangs up.
+ *
+ *     entries          every entry with when it is next due
+ *     jobs             the jobs that are running
+ *     run ENTRY        run ENTRY now
+ *     pause [ENTRY]    stop starting ENTRY, or anything at all
+ *     resume [ENTRY]   undo pause
+ *     reload           read the crontabs again
+ *
+ * ENTRY is FILE:LINE, or just LINE when only one crontab has that line.
+ */
+#[derive(Debug, Clone, PartialEq)]
+pub enum Command {
+    Entries,
+    Jobs,
+    Run(Target),
+    Pause(Option<Target>),
+    Resume(Option<Target>),
+    Reload,
+}
+
+impl Command {
+    pub fn parse(line: &str) -> Result<Self, Error> {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        Ok(match words.as_slice() {
+            ["entries"] => Command::Entries,
+            ["jobs"] => Command::Jobs,
+            ["run", entry] => Command::Run(Target::parse(entry)?),
+            ["pause"] => Command::Pause(None),
+            ["pause", entry] => Command::Pause(Some(Target::parse(entry)?)),
+            ["resume"] => Command::Resume(None),
+            ["resume", entry] => Command::Resume(Some(Target::parse(entry)?)),
+            ["reload"] => Command::Reload,
+            [] => bail!("no command"),
+            _ => bail!("unknown command {:?}", line.trim()),
+        })
+    }
+}
+
+/// The entry a command is about.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Target {
+    pub file: Option<String>,
+    pub line: usize,
+}
+
+impl Target {
+    pub fn parse(word: &str) -> Result<Self, Error> {
+        let (file, line) = match word.rfind(':') {
+            Some(at) => (Some(word[..at].to_owned()), &word[at + 1..]),
+            None => (None, word),
+        };
+        match line.parse() {
+            Ok(line) => Ok(Target { file, line }),
+            Err(_) => bail!("expected LINE or FILE:LINE, got {:?}", word),
+        }
+    }
+
+    /// The one entry of `entries` this is about.
+    pub fn find<'a>(&self, entries: &'a [Entry]) -> Result<&'a Entry, Error> {
+        let mut found = entries.iter().filter(|entry| {
+            entry.line() == self.line
+                && self.file.as_ref().is_none_or(|file| entry.file() == Some(file.as_str()))
+        });
+        match (found.next(), found.next()) {
+            (Some(entry), None) => Ok(entry),
+            (Some(_), Some(_)) => bail!(
+                "more than one crontab has an entry on line {}, say which as FILE:{}",
+                self.line,
+                self.line
+            ),
+            (None, _) => match &self.file {
+                Some(file) => bail!("there is no entry on line {} of {}", self.line, file),
+                None => bail!("there is no entry on line {}", self.line),
+            },
+        }
+    }
+}
+
+/// What has been paused over the control socket. Entries are remembered as
+/// written in the crontab, so that they stay paused across reloads.
+#[derive(Debug, Default)]
+pub struct Paused {
+    pub scheduler: bool,
+    pub entries: HashSet<String>,
+}
+
+impl Paused {
+    pub fn is_paused(&self, entry: &Entry) -> bool {
+        self.scheduler || self.entries.contains(entry.source())
+    }
+}
+
+/*
+ * The listening end of the control socket. The socket is only accessible to
+ * the user crondy runs as, and connections from anyone but that user and
+ * root are turned away as well, in case the socket ends up somewhere with
+ * looser permissions.
+ */
+pub struct Server {
+    path: PathBuf,
+    listener: UnixListener,
+}
+
+impl Server {
+    pub fn bind(path: &Path) -> Result<Self, Error> {
+        let error = |error: io::Error| format_err!("could not bind {}: {}", path.display(), error);
+        // a socket left behind by an earlier crondy is in the way, but
+        // anything else there was probably meant to be kept
+        if UnixStream::connect(path).is_err() {
+            match fs::symlink_metadata(path) {
+                Ok(metadata) if metadata.file_type().is_socket() => {
+                    fs::remove_file(path).map_err(error)?
+                }
+                Ok(_) => bail!("could not bind {}: not a socket", path.display()),
+                Err(ref missing) if missing.kind() == io::ErrorKind::NotFound => (),
+                Err(other) => return Err(error(other)),
+            }
+        }
+        let old_umask = unsafe { libc::umask(0o177) };
+        let listener = UnixListener::bind(path);
+        unsafe {
+            libc::umask(old_umask);
+        }
+        let listener = listener.map_err(error)?;
+        listener.set_nonblocking(true).map_err(error)?;
+        Ok(Server {
+            path: path.to_owned(),
+            listener,
+        })
+    }
+
+    /// The next request, if anyone has sent one. Does not block.
+    pub fn accept(&self) -> Option<Request> {
+        loop {
+            let stream = match self.listener.accept() {
+                Ok((stream, _)) => stream,
+                Err(ref error) if error.kind() == io::ErrorKind::WouldBlock => return None,
+                Err(error) => {
+                    warn!("could not accept control connection: {}", error);
+                    return None;
+                }
+            };
+            match Request::read(stream) {
+                Ok(request) => return Some(request),
+                Err(error) => warn!("bad control request: {}", error),
+            }
+        }
+    }
+}
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+/// A command from a client, waiting for its answer.
+pub struct Request {
+    pub command: Result<Command, Error>,
+    stream: UnixStream,
+}
+
+impl Request {
+    fn read(stream: UnixStream) -> Result<Self, Error> {
+        let uid = peer_uid(&stream)?;
+        let allowed = unsafe { libc::geteuid() };
+        if uid != 0 && uid != allowed {
+            let _ = (&stream).write_all(b"error permission denied\n");
+            bail!("refused connection from uid {}", uid);
+        }
+        // a client that takes its time must not hold up the scheduler
+        stream.set_nonblocking(false)?;
+        stream.set_read_timeout(Some(Duration::from_secs(1)))?;
+        stream.set_write_timeout(Some(Duration::from_secs(1)))?;
+        let mut line = String::new();
+        BufReader::new(&stream).read_line(&mut line)?;
+        debug!("control request from uid {}: {:?}", uid, line.trim_end());
+        Ok(Request {
+            command: Command::parse(&line),
+            stream,
+        })
+    }
+
+    pub fn reply(mut self, result: Result<Vec<String>, Error>) {
+        let mut reply = match result {
+            Ok(lines) => {
+                let mut reply = "ok\n".to_owned();
+                for line in lines {
+                    reply.push_str(&line);
+                    reply.push('\n');
+                }
+                reply
+            }
+            Err(error) => format!("error {}\n", error),
+        };
+        if let Err(error) = self.stream.write_all(reply.as_bytes()) {
+            reply.truncate(reply.find('\n').unwrap_or(0));
+            warn!("could not answer control request ({}): {}", reply, error);
+        }
+    }
+}
+
+fn peer_uid(stream: &UnixStream) -> io::Result<u32> {
+    let mut credentials: libc::ucred = unsafe { std::mem::zeroed() };
+    let mut len = std::mem::size_of::<libc::ucred>() as libc::socklen_t;
+    let result = unsafe {
+        libc::getsockopt(
+            stream.as_raw_fd(),
+            libc::SOL_SOCKET,
+            libc::SO_PEERCRED,
+            &mut credentials as *mut libc::ucred as *mut libc::c_void,
+            &mut len,
+        )
+    };
+    if result == 0 {
+        Ok(credentials.uid)
+    } else {
+        Err(io::Error::last_os_error())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_files::temporary;
+    use crondy::crontab::Crontab;
+    use std::io::Read;
+    use std::os::unix::fs::PermissionsExt;
+    use std::thread;
+
+    fn line(file: Option<&str>, line: usize) -> Target {
+        Target {
+            file: file.map(str::to_owned),
+            line,
+        }
+    }
+
+    #[test]
+    fn parses_commands() {
+        assert_eq!(Command::parse("entries\n").unwrap(), Command::Entries);
+        assert_eq!(Command::parse("  run 12 ").unwrap(), Command::Run(line(None, 12)));
+        assert_eq!(Command::parse("pause").unwrap(), Command::Pause(None));
+        assert_eq!(
+            Command::parse("resume /etc/cron.d/backup:3").unwrap(),
+            Command::Resume(Some(line(Some("/etc/cron.d/backup"), 3)))
+        );
+    }
+
+    #[test]
+    fn rejects_bad_commands() {
+        assert!(Command::parse("").is_err());
+        assert!(Command::parse("run").is_err());
+        assert!(Command::parse("run backup").is_err());
+        assert!(Command::parse("run backup:").is_err());
+        assert!(Command::parse("explode").is_err());
+        assert!(Command::parse("jobs 1").is_err());
+    }
+
+    #[test]
+    fn finds_targets() {
+        let first = Crontab::parse("@daily backup\n@hourly rotate").unwrap().in_file("a");
+        let second = Crontab::parse("@daily report").unwrap().in_file("b");
+        let tab = Crontab::merge(vec![first, second]);
+        let find = |target: Target| target.find(tab.entries()).map(Entry::source);
+        assert_eq!(find(line(None, 2)).unwrap(), "@hourly rotate");
+        assert_eq!(find(line(Some("b"), 1)).unwrap(), "@daily report");
+        assert!(find(line(None, 1)).is_err());
+        assert!(find(line(Some("b"), 2)).is_err());
+    }
+
+    #[test]
+    fn pauses_entries_and_everything() {
+        let tab = Crontab::parse("@daily backup\n@hourly rotate").unwrap();
+        let mut paused = Paused::default();
+        paused.entries.insert("@daily backup".to_owned());
+        assert!(paused.is_paused(&tab.entries()[0]));
+        assert!(!paused.is_paused(&tab.entries()[1]));
+        paused.scheduler = true;
+        assert!(paused.is_paused(&tab.entries()[1]));
+    }
+
+    #[test]
+    fn answers_requests() {
+        let path = temporary("control");
+        let server = Server::bind(&path).unwrap();
+        let mode = fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+
+        let client = {
+            let path = path.clone();
+            thread::spawn(move || {
+                let mut stream = UnixStream::connect(&path).unwrap();
+                stream.write_all(b"run 4\n").unwrap();
+                let mut reply = String::new();
+                stream.read_to_string(&mut reply).unwrap();
+                reply
+            })
+        };
+        let request = loop {
+            match server.accept() {
+                Some(request) => break request,
+                None => thread::sleep(Duration::from_millis(10)),
+            }
+        };
+        assert_eq!(request.command.as_ref().unwrap(), &Command::Run(line(None, 4)));
+        request.reply(Ok(vec!["started".to_owned()]));
+        assert_eq!(client.join().unwrap(), "ok\nstarted\n");
+
+        drop(server);
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn leaves_other_files_alone() {
+        let path = temporary("not-a-socket");
+        fs::write(&path, "keep me").unwrap();
+        let error = Server::bind(&path).err().unwrap();
+        assert!(error.to_string().ends_with("not a socket"));
+        assert_eq!(fs::read_to_string(&path).unwrap(), "keep me");
+        fs::remove_file(&path).unwrap();
+    }
+}
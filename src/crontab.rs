@@ -1,7 +1,12 @@
+use std::error;
+use std::fmt;
+
 use log::debug;
 
-use crate::schedule::Schedule;
-use failure::{Error, bail};
+use crate::clock::{self, Moment};
+use crate::options::Options;
+use crate::schedule::{Schedule, ScheduleError};
+use crate::user::User;
 use nom::{
     alt, alt_complete, call, char, complete, error_position, many1, map, named, none_of, preceded,
     rest, separated_pair, tuple, tuple_parser, AsChar, InputTakeAtPosition,
@@ -12,86 +17,569 @@ pub struct Crontab {
     entries: Vec<Entry>,
 }
 
+/// Whether entries name the user to run as between the schedule and the
+/// command, as in /etc/crontab, or not, as in a user's own crontab.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Syntax {
+    User,
+    System,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    Warning,
+    Error,
+}
+
+/// Something wrong, or probably wrong, on a line of a crontab.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Problem {
+    pub line: usize,
+    pub severity: Severity,
+    pub message: String,
+}
+
+impl Problem {
+    fn new(line: usize, severity: Severity, message: impl Into<String>) -> Self {
+        Problem {
+            line,
+            severity,
+            message: message.into(),
+        }
+    }
+}
+
+/// Why a crontab was refused: the first thing wrong with it, and where.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CrontabError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for CrontabError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl error::Error for CrontabError {}
+
 impl Crontab {
-    pub fn parse(input: &str) -> Result<Self, Error> {
+    pub fn parse(input: &str) -> Result<Self, CrontabError> {
+        Self::parse_as(input, Syntax::User)
+    }
+
+    pub fn parse_as(input: &str, syntax: Syntax) -> Result<Self, CrontabError> {
+        let (crontab, problems) = Self::read(input, syntax);
+        match problems.into_iter().find(|problem| problem.severity == Severity::Error) {
+            Some(problem) => Err(CrontabError {
+                line: problem.line,
+                message: problem.message,
+            }),
+            None => Ok(crontab),
+        }
+    }
+
+    /*
+     * Reads whatever lines of `input` make sense, and turns the ones that
+     * don't into errors. Variables that only look like settings are passed
+     * on to jobs like any other, with a warning in case of a typo.
+     */
+    fn read(input: &str, syntax: Syntax) -> (Self, Vec<Problem>) {
         let mut entries = Vec::new();
+        let mut problems = Vec::new();
         let mut env = Vec::new();
-        for line in input.lines() {
-            match parse_line(line) {
-                Ok(("", line)) => match line {
-                    Some(CrontabLine::Entry(mut e)) => {
-                        e.envp = env.clone();
-                        entries.push(e);
+        let mut options = Options::default();
+        for (number, line_text) in input.lines().enumerate() {
+            let line = number + 1;
+            match parse_line(line_text) {
+                Ok(("", Some(CrontabLine::Entry(mut e)))) => {
+                    e.line = line;
+                    e.source = line_text.trim().to_owned();
+                    e.envp = env.clone();
+                    e.options = options.clone();
+                    if syntax == Syntax::System {
+                        match split_user(&e.cmd) {
+                            Some((user, cmd)) => {
+                                e.user = Some(user);
+                                e.cmd = cmd;
+                            }
+                            None => {
+                                problems.push(Problem::new(
+                                    line,
+                                    Severity::Error,
+                                    "expected a user name and a command after the schedule",
+                                ));
+                                continue;
+                            }
+                        }
+                    }
+                    entries.push(*e);
+                }
+                Ok(("", Some(CrontabLine::Env(n, v)))) => {
+                    if Options::is_setting(&n) {
+                        if let Err(error) = options.set(&n, &v) {
+                            problems.push(Problem::new(line, Severity::Error, error.to_string()));
+                        }
+                    } else {
+                        if n.starts_with("CRONDY_") {
+                            problems.push(Problem::new(
+                                line,
+                                Severity::Warning,
+                                format!("{} is not a setting, passing it on to jobs", n),
+                            ));
+                        }
+                        env.push(format!("{}={}", n, v));
                     }
-                    Some(CrontabLine::Env(n, v)) => env.push(format!("{}={}", n, v)),
-                    None => debug!("parsed an empty line"),
-                },
-                Ok((remaining, _)) => bail!("crontab line parser failed to consume all input (leftovers: {})", remaining),
-                Err(e) => bail!("crontab line parser encountered an error: {}", e)
+                }
+                Ok(("", None)) => debug!("parsed an empty line"),
+                Ok((remaining, _)) => problems.push(Problem::new(
+                    line,
+                    Severity::Error,
+                    format!("could not make sense of {:?}", remaining),
+                )),
+                Err(e) => {
+                    debug!("line {}: {}", line, e);
+                    problems.push(Problem::new(
+                        line,
+                        Severity::Error,
+                        "expected an entry, an environment setting or a comment",
+                    ))
+                }
             }
         }
-        Ok(Self { entries })
+        (Self { entries }, problems)
     }
 
-    pub fn validate(&self) -> Result<(), Error> {
+    pub fn validate(&self) -> Result<(), CrontabError> {
         for entry in self.entries.iter() {
-            entry.schedule.validate()?;
+            entry.validate().map_err(|error| CrontabError {
+                line: entry.line,
+                message: error.to_string(),
+            })?;
         }
         Ok(())
     }
 
-    pub fn run_reboot_jobs(&self) {
-        debug!("running reboot jobs");
-        for entry in self.entries.iter() {
-            if let Schedule::Reboot = entry.schedule {
-                debug!("scheduling reboot job: {:?}", &entry);
+    /*
+     * Everything wrong with `input`, in line order. Errors are what `parse_as`
+     * and `validate` would refuse it for; warnings are things that work but
+     * are unlikely to do what was meant.
+     */
+    pub fn check(input: &str, syntax: Syntax) -> Vec<Problem> {
+        let (crontab, mut problems) = Self::read(input, syntax);
+        let now = clock::now();
+        for (index, entry) in crontab.entries.iter().enumerate() {
+            let line = entry.line;
+            let mut warn = |message: String| {
+                problems.push(Problem::new(line, Severity::Warning, message))
+            };
+            // only valid schedules can be compared by what they mean
+            let valid = |e: &Entry| e.validate().is_ok();
+            if let Some(earlier) = crontab.entries[..index].iter().filter(|e| valid(e)).find(|e| {
+                valid(entry)
+                    && e.schedule.equivalent(&entry.schedule)
+                    && (&e.user, &e.cmd, &e.stdin) == (&entry.user, &entry.cmd, &entry.stdin)
+            }) {
+                warn(format!("same as line {}, so it runs twice", earlier.line));
+            }
+            match &entry.user {
+                Some(user) if User::by_name(user).is_err() => {
+                    warn(format!("there is no user {:?} here", user))
+                }
+                None if looks_like_user(&entry.cmd) => warn(format!(
+                    "{:?} is a user name; is this meant to be a system crontab?",
+                    entry.cmd.split_whitespace().next().unwrap_or_default()
+                )),
+                _ => (),
+            }
+            if let Schedule::When(when) = &entry.schedule {
+                if !when.day_of_month.is_star() && !when.day_of_week.is_star() {
+                    warn(format!(
+                        "runs on days that match the day of month *or* the day of week: {}",
+                        entry.schedule.describe()
+                    ));
+                }
             }
+            match entry.validate() {
+                Err(error) => problems.push(Problem::new(line, Severity::Error, error.to_string())),
+                Ok(()) => {
+                    if entry.schedule != Schedule::Reboot && entry.schedule.next_after(now).is_none() {
+                        problems.push(Problem::new(
+                            line,
+                            Severity::Warning,
+                            format!(
+                                "never runs, there is no such date ({})",
+                                entry.schedule.describe()
+                            ),
+                        ));
+                    }
+                }
+            }
+        }
+        if let Some(last) = crontab.entries.last() {
+            if !input.ends_with('\n') && last.line == input.lines().count() {
+                problems.push(Problem::new(
+                    last.line,
+                    Severity::Warning,
+                    "no newline at the end of the file; other crons ignore this line",
+                ));
+            }
+        }
+        problems.sort_by_key(|problem| problem.line);
+        problems
+    }
+
+    /// Note down that the entries were read from `path`.
+    pub fn in_file(mut self, path: &str) -> Self {
+        for entry in &mut self.entries {
+            entry.file = Some(path.to_owned());
+        }
+        self
+    }
+
+    /// All the entries of `crontabs` in one, in order.
+    pub fn merge(crontabs: Vec<Crontab>) -> Self {
+        Crontab {
+            entries: crontabs.into_iter().flat_map(|crontab| crontab.entries).collect(),
+        }
+    }
+
+    pub fn entries(&self) -> &[Entry] {
+        &self.entries
+    }
+
+    pub fn reboot_jobs(&self) -> Vec<&Entry> {
+        self.entries
+            .iter()
+            .filter(|entry| entry.schedule == Schedule::Reboot)
+            .collect()
+    }
+
+    /// The entries due at `moment`. `do_wild` and `do_non_wild` select
+    /// between wildcard and fixed-time entries, see `When::is_wildcard`.
+    pub fn find_jobs(&self, moment: &Moment, do_wild: bool, do_non_wild: bool) -> Vec<&Entry> {
+        self.entries
+            .iter()
+            .filter(|entry| match &entry.schedule {
+                Schedule::Reboot => false,
+                Schedule::When(when) => {
+                    when.matches(moment)
+                        && if when.is_wildcard() {
+                            do_wild
+                        } else {
+                            do_non_wild
+                        }
+                }
+            })
+            .collect()
+    }
+}
+
+/*
+ * A crontab prints in canonical form: every entry as `Entry` prints it,
+ * after whatever environment variables and settings changed since the entry
+ * before. That parses back to the same entries, if on different lines, as
+ * long as they were read from a single file: there is no way to unset the
+ * environment of one file for the entries of the next.
+ */
+impl fmt::Display for Crontab {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut envp: &[String] = &[];
+        let mut settings = Options::default().settings();
+        for entry in &self.entries {
+            for var in entry.envp.strip_prefix(envp).unwrap_or(&entry.envp) {
+                let (name, value) = var.split_at(var.find('=').unwrap_or(var.len()));
+                let value = value.get(1..).unwrap_or_default();
+                if value.starts_with(char::is_whitespace) || is_quoted(value) {
+                    writeln!(f, "{}=\"{}\"", name, value)?;
+                } else {
+                    writeln!(f, "{}={}", name, value)?;
+                }
+            }
+            envp = &entry.envp;
+            let entry_settings = entry.options.settings();
+            for (setting, before) in entry_settings.iter().zip(&settings) {
+                if setting != before {
+                    writeln!(f, "{}={}", setting.0, setting.1)?;
+                }
+            }
+            settings = entry_settings;
+            writeln!(f, "{}", entry)?;
         }
+        Ok(())
     }
 }
 
 #[derive(Debug, PartialEq)]
 enum CrontabLine {
-    Entry(Entry),
+    Entry(Box<Entry>),
     Env(String, String),
 }
 
-#[derive(Debug, PartialEq)]
-struct Entry {
+#[derive(Debug, Clone, PartialEq)]
+pub struct Entry {
+    file: Option<String>,
+    line: usize,
+    source: String,
+    user: Option<String>, // in system crontabs
     envp: Vec<String>,
     cmd: String,
+    stdin: Option<String>,
     schedule: Schedule,
+    options: Options,
+}
+
+impl Entry {
+    /// Where the entry is in its crontab, counting from 1. This is what
+    /// identifies an entry to the rest of the daemon.
+    pub fn line(&self) -> usize {
+        self.line
+    }
+
+    /// The crontab the entry was read from, if it came from a file.
+    pub fn file(&self) -> Option<&str> {
+        self.file.as_deref()
+    }
+
+    /// Whether `other` is this entry, perhaps as read before a reload.
+    pub fn is_same(&self, other: &Entry) -> bool {
+        self.line == other.line && self.file == other.file
+    }
+
+    /// Where the entry is, as FILE:LINE, or `line LINE` if it didn't come
+    /// from a file.
+    pub fn location(&self) -> String {
+        match &self.file {
+            Some(file) => format!("{}:{}", file, self.line),
+            None => format!("line {}", self.line),
+        }
+    }
+
+    /// The entry as written in the crontab, which identifies it across
+    /// restarts (unlike its line number, which changes when lines are added
+    /// above it).
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    pub fn options(&self) -> &Options {
+        &self.options
+    }
+
+    /// Whether the schedule is valid, allowing for `CRONDY_WRAP_RANGES`.
+    pub fn validate(&self) -> Result<(), ScheduleError> {
+        if self.options.wrap_ranges {
+            self.schedule.validate_wrapping()
+        } else {
+            self.schedule.validate()
+        }
+    }
+
+    pub fn schedule(&self) -> &Schedule {
+        &self.schedule
+    }
+
+    /// The command handed to the shell, up to the first unescaped `%`.
+    pub fn command(&self) -> &str {
+        &self.cmd
+    }
+
+    /// What the job reads on stdin: everything after the first unescaped
+    /// `%`, with any further ones turned into newlines.
+    pub fn stdin(&self) -> Option<&str> {
+        self.stdin.as_deref()
+    }
+
+    /*
+     * The environment a job really runs with. As in Vixie cron, it starts
+     * out with
+     *
+     *     PATH=/usr/bin:/bin
+     *     SHELL=/bin/sh
+     *     HOME=<owner's home directory>
+     *     LOGNAME=<owner's name>
+     *     USER=<owner's name>
+     *
+     * and then gets the variables set in the crontab above the entry, in
+     * order. PATH, SHELL and HOME may be overridden that way; LOGNAME and
+     * USER always name the user the job runs as.
+     */
+    pub fn environment(&self, owner: &User) -> Vec<(String, String)> {
+        let mut env = vec![
+            ("PATH".to_owned(), DEFAULT_PATH.to_owned()),
+            ("SHELL".to_owned(), DEFAULT_SHELL.to_owned()),
+            ("HOME".to_owned(), owner.home.clone()),
+        ];
+        for var in self.envp.iter() {
+            let mut pair = var.splitn(2, '=');
+            if let (Some(name), Some(value)) = (pair.next(), pair.next()) {
+                if name == "LOGNAME" || name == "USER" {
+                    continue;
+                }
+                env.retain(|(n, _)| n != name);
+                env.push((name.to_owned(), value.to_owned()));
+            }
+        }
+        env.push(("LOGNAME".to_owned(), owner.name.clone()));
+        env.push(("USER".to_owned(), owner.name.clone()));
+        env
+    }
+}
+
+/// The entry as a line of a crontab, with the input joined back onto the
+/// command with `%`.
+impl fmt::Display for Entry {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} ", self.schedule)?;
+        if let Some(user) = &self.user {
+            write!(f, "{} ", user)?;
+        }
+        f.write_str(&escape_percents(&self.cmd))?;
+        if let Some(stdin) = &self.stdin {
+            // the newline that reading it adds, unless it had one already
+            let stdin = match stdin.strip_suffix('\n') {
+                Some(stripped) if !stripped.ends_with('\n') => stripped,
+                _ => stdin,
+            };
+            write!(f, "%{}", escape_percents(stdin).replace('\n', "%"))?;
+        }
+        Ok(())
+    }
+}
+
+/*
+ * In a system crontab the command starts with the name of the user to run it
+ * as.
+ */
+fn split_user(cmd: &str) -> Option<(String, String)> {
+    let cmd = cmd.trim_start();
+    let end = cmd.find(char::is_whitespace)?;
+    let rest = cmd[end..].trim_start();
+    if rest.is_empty() {
+        None
+    } else {
+        Some((cmd[..end].to_owned(), rest.to_owned()))
+    }
+}
+
+/// Whether a command starts with the name of root or of someone who can log
+/// in, as if it came from a system crontab. System accounts such as `backup`
+/// or `mail` are more likely to be the command of the same name.
+fn looks_like_user(cmd: &str) -> bool {
+    match split_user(cmd).map(|(user, _)| User::by_name(&user)) {
+        Some(Ok(user)) => user.uid == 0 || user.can_log_in(),
+        _ => false,
+    }
+}
+
+const DEFAULT_PATH: &str = "/usr/bin:/bin";
+const DEFAULT_SHELL: &str = "/bin/sh";
+
+/*
+ * Splits the command part of a crontab line the way Vixie cron does: the
+ * first unescaped % ends the command and starts the job's input, where every
+ * further unescaped % is a newline. \% is a literal % in both parts; other
+ * backslashes are left alone. The input always ends with a newline.
+ */
+fn split_command(raw: &str) -> (String, Option<String>) {
+    let mut cmd = String::new();
+    let mut chars = raw.trim_start().chars();
+    let mut escaped = false;
+    while let Some(c) = chars.next() {
+        if escaped {
+            if c != '%' {
+                cmd.push('\\');
+            }
+            cmd.push(c);
+            escaped = false;
+        } else if c == '\\' {
+            escaped = true;
+        } else if c == '%' {
+            return (cmd, Some(percents_to_newlines(chars.as_str())));
+        } else {
+            cmd.push(c);
+        }
+    }
+    if escaped {
+        cmd.push('\\');
+    }
+    (cmd, None)
+}
+
+/*
+ * The other way around: % becomes \%. A backslash is always followed by
+ * whatever it was followed by when it was read, which is not %, so it is
+ * left alone along with that.
+ */
+fn escape_percents(text: &str) -> String {
+    let mut escaped = String::new();
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '%' => escaped.push_str("\\%"),
+            '\\' => {
+                escaped.push(c);
+                escaped.extend(chars.next());
+            }
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn percents_to_newlines(raw: &str) -> String {
+    let mut input = String::new();
+    let mut escaped = false;
+    for c in raw.chars() {
+        if escaped {
+            if c != '%' {
+                input.push('\\');
+            }
+            input.push(c);
+            escaped = false;
+        } else if c == '\\' {
+            escaped = true;
+        } else if c == '%' {
+            input.push('\n');
+        } else {
+            input.push(c);
+        }
+    }
+    if escaped {
+        input.push('\\');
+    }
+    if !input.ends_with('\n') {
+        input.push('\n');
+    }
+    input
 }
 
 named!(
     parse_line<&str, Option<CrontabLine>>,
     alt_complete!(
-        map!(parse_entry, |e| Some(CrontabLine::Entry(e))) |
-        map!(parse_env, |l| Some(l)) |
+        map!(parse_entry, |e| Some(CrontabLine::Entry(Box::new(e)))) |
+        map!(parse_env, Some) |
         map!(parse_comment, |_| None) |
         map!(empty_line, |_| None)
     )
 );
 
 fn empty_line(input: &str) -> nom::IResult<&str, &str> {
-    if input.len() == 0 {
+    if input.is_empty() || input.chars().all(|c| c == ' ' || c == '\t') {
         Ok(("", input))
     } else {
-        if input.chars().all(|c| c == ' ' || c == '\t') {
-            Ok(("", input))
-        } else {
-            input.split_at_position(|item| {
-                let c = item.clone().as_char();
-                !(c == ' ' || c == '\t')
-            })
-        }
+        input.split_at_position(|item| {
+            let c = item.as_char();
+            !(c == ' ' || c == '\t')
+        })
     }
 }
 
 named!(parse_entry<&str, Entry>, map!(
-    tuple!(Schedule::parse, map!(rest, |s| s.to_owned())),
-    |(schedule, cmd)|
-        Entry {envp: Vec::new(), cmd, schedule}
+    tuple!(Schedule::parse, map!(rest, split_command)),
+    |(schedule, (cmd, stdin))|
+        Entry {file: None, line: 0, source: String::new(), user: None, envp: Vec::new(), cmd, stdin, schedule, options: Options::default()}
     ));
 
 named!(
@@ -100,20 +588,28 @@ named!(
         separated_pair!(many1!(none_of!("=")), char!('='), rest),
         |(n, v)| {
             let name = n.into_iter().collect::<String>();
-            let mut value = v.trim_left();
-            if (value.starts_with('\'') && value.ends_with('\'')) || (value.starts_with('"') && value.ends_with('"')) {
+            let mut value = v.trim_start();
+            if is_quoted(value) {
                 value = &value[1..(value.len() - 1)];
             }
-            CrontabLine::Env(name.trim_right().to_string(), value.to_string())
+            CrontabLine::Env(name.trim_end().to_string(), value.to_string())
         }
     )
 );
 
+fn is_quoted(value: &str) -> bool {
+    value.len() >= 2
+        && ((value.starts_with('\'') && value.ends_with('\''))
+            || (value.starts_with('"') && value.ends_with('"')))
+}
+
 named!(parse_comment<&str, &str>, preceded!(char!('#'), rest));
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::test_helpers::below;
+    use quickcheck::{quickcheck, Arbitrary, Gen};
 
     #[test]
     fn parse_crontab_blanks() {
@@ -128,7 +624,9 @@ mod tests {
     #[test]
     fn parse_crontab_comment_and_schedule() {
         let tab = Crontab::parse("\n# Howdy pardner\n* * * * * this is a command\n").unwrap();
-        assert!(tab.entries.len() == 1)
+        assert!(tab.entries.len() == 1);
+        assert_eq!(tab.entries[0].line(), 3);
+        assert_eq!(tab.entries[0].source(), "* * * * * this is a command");
     }
 
     #[test]
@@ -138,6 +636,122 @@ mod tests {
         assert_eq!(tab.entries[1].envp, vec!["FOO=BAR".to_owned()]);
     }
 
+    #[test]
+    fn parse_crontab_keeps_settings_out_of_env() {
+        let tab = Crontab::parse("CRONDY_WRAP_RANGES=yes\n* * * * * first").unwrap();
+        assert_eq!(tab.entries[0].envp, Vec::<String>::new());
+        assert!(tab.entries[0].options.wrap_ranges);
+    }
+
+    #[test]
+    fn parse_crontab_passes_unknown_settings_on() {
+        let tab = Crontab::parse("CRONDY_FROB=yes\n* * * * * first").unwrap();
+        assert_eq!(tab.entries[0].envp, vec!["CRONDY_FROB=yes".to_owned()]);
+        assert!(Crontab::parse("CRONDY_TIMEOUT=soon\n* * * * * first").is_err());
+    }
+
+    #[test]
+    fn validate_crontab_refuses_reversed_ranges_by_default() {
+        let tab = Crontab::parse("0 22-2 * * * night shift").unwrap();
+        assert_eq!(
+            tab.validate(),
+            Err(CrontabError {
+                line: 1,
+                message: "hour range out of order (start 22 came after end 2)".to_owned(),
+            })
+        );
+    }
+
+    #[test]
+    fn validate_crontab_allows_reversed_ranges_when_wrapping() {
+        let tab = Crontab::parse("CRONDY_WRAP_RANGES=yes\n0 22-2 * * FRI-MON night shift").unwrap();
+        assert!(tab.validate().is_ok());
+    }
+
+    #[test]
+    fn parse_crontab_reports_line_numbers() {
+        let error = Crontab::parse("# fine\n* * * * * fine\n* * * oops").unwrap_err();
+        assert!(error.to_string().starts_with("line 3: "));
+    }
+
+    #[test]
+    fn parse_system_crontab_takes_user() {
+        let tab = Crontab::parse_as("0 4 * * * root  run-parts /etc/cron.daily", Syntax::System)
+            .unwrap();
+        assert_eq!(tab.entries[0].user.as_deref(), Some("root"));
+        assert_eq!(tab.entries[0].command(), "run-parts /etc/cron.daily");
+        assert!(Crontab::parse_as("0 4 * * * root", Syntax::System).is_err());
+    }
+
+    fn check(input: &str, syntax: Syntax) -> Vec<(usize, Severity)> {
+        Crontab::check(input, syntax)
+            .into_iter()
+            .map(|problem| (problem.line, problem.severity))
+            .collect()
+    }
+
+    #[test]
+    fn check_finds_nothing_wrong() {
+        assert_eq!(check("# backups\nMAILTO=ops\n0 3 * * * backup\n", Syntax::User), vec![]);
+    }
+
+    #[test]
+    fn check_does_not_compare_invalid_schedules() {
+        assert_eq!(
+            check("0 1 * * * a\n0 99-2 * * * b\n0 99-2 * * * b\n", Syntax::User),
+            vec![(2, Severity::Error), (3, Severity::Error)]
+        );
+    }
+
+    #[test]
+    fn check_reports_every_error() {
+        assert_eq!(
+            check("* * * oops\n61 * * * * late\nCRONDY_RETRIES=x\n0 1 * * * ok\n", Syntax::User),
+            vec![(1, Severity::Error), (2, Severity::Error), (3, Severity::Error)]
+        );
+    }
+
+    #[test]
+    fn check_warns_about_surprises() {
+        let input = "0 0 31 2 * never\n0 0 1 * 1 either\n@daily twice\n@midnight twice\n\
+                     0,30 * * * * poll\n*/30 * * * * poll\n";
+        assert_eq!(
+            check(input, Syntax::User),
+            vec![
+                (1, Severity::Warning),
+                (2, Severity::Warning),
+                (4, Severity::Warning),
+                (6, Severity::Warning)
+            ]
+        );
+    }
+
+    #[test]
+    fn check_warns_about_unknown_settings() {
+        assert_eq!(
+            check("CRONDY_FROB=1\nCRONDY_LOG=debug\n0 1 * * * ok\n", Syntax::User),
+            vec![(1, Severity::Warning), (2, Severity::Warning)]
+        );
+    }
+
+    #[test]
+    fn check_warns_about_missing_newline() {
+        assert_eq!(check("@daily backup", Syntax::User), vec![(1, Severity::Warning)]);
+        assert_eq!(check("@daily backup\n# done", Syntax::User), vec![]);
+    }
+
+    #[test]
+    fn check_knows_system_from_user_syntax() {
+        let input = "@daily root backup\n";
+        assert_eq!(check(input, Syntax::System), vec![]);
+        assert_eq!(check(input, Syntax::User), vec![(1, Severity::Warning)]);
+        assert_eq!(check("0 3 * * * nobody --all /srv\n", Syntax::User), vec![]);
+        assert_eq!(
+            check("@daily nosuchuser backup\n", Syntax::System),
+            vec![(1, Severity::Warning)]
+        );
+    }
+
     #[test]
     fn parse_line_nothing() {
         assert_parses_to_exactly!(parse_line(""), None)
@@ -162,7 +776,99 @@ mod tests {
     fn parse_entry_all_stars() {
         let (rem, entry) = parse_entry("* * * * * this is a command").unwrap();
         assert_eq!(rem, "");
-        assert_eq!(entry.cmd, " this is a command".to_owned());
+        assert_eq!(entry.command(), "this is a command");
+        assert_eq!(entry.stdin(), None);
+    }
+
+    #[test]
+    fn parse_entry_splits_stdin_at_percent() {
+        let (_, entry) = parse_entry("* * * * * mail -s hi root%Dear root,%%bye").unwrap();
+        assert_eq!(entry.command(), "mail -s hi root");
+        assert_eq!(entry.stdin(), Some("Dear root,\n\nbye\n"));
+    }
+
+    #[test]
+    fn split_command_unescapes_percent() {
+        assert_eq!(
+            split_command(" date +\\%Y-\\%m"),
+            ("date +%Y-%m".to_owned(), None)
+        );
+        assert_eq!(
+            split_command("cat%100\\%%done"),
+            ("cat".to_owned(), Some("100%\ndone\n".to_owned()))
+        );
+    }
+
+    #[test]
+    fn split_command_keeps_other_backslashes() {
+        assert_eq!(
+            split_command("echo a\\tb\\"),
+            ("echo a\\tb\\".to_owned(), None)
+        );
+        assert_eq!(
+            split_command("cat%a\\nb"),
+            ("cat".to_owned(), Some("a\\nb\n".to_owned()))
+        );
+    }
+
+    #[test]
+    fn split_command_with_trailing_percent() {
+        assert_eq!(split_command("cat%"), ("cat".to_owned(), Some("\n".to_owned())));
+    }
+
+    fn owner() -> User {
+        User {
+            name: "alice".to_owned(),
+            uid: 1000,
+            home: "/home/alice".to_owned(),
+            shell: "/bin/zsh".to_owned(),
+        }
+    }
+
+    #[test]
+    fn environment_defaults() {
+        let tab = Crontab::parse("@reboot true").unwrap();
+        assert_eq!(
+            tab.entries[0].environment(&owner()),
+            vec![
+                ("PATH".to_owned(), "/usr/bin:/bin".to_owned()),
+                ("SHELL".to_owned(), "/bin/sh".to_owned()),
+                ("HOME".to_owned(), "/home/alice".to_owned()),
+                ("LOGNAME".to_owned(), "alice".to_owned()),
+                ("USER".to_owned(), "alice".to_owned()),
+            ]
+        );
+    }
+
+    #[test]
+    fn environment_overrides_defaults_but_not_user() {
+        let tab = Crontab::parse(
+            "PATH=/opt/bin\nFOO=bar\nPATH=/usr/local/bin:/usr/bin\nLOGNAME=root\n@reboot true",
+        )
+        .unwrap();
+        assert_eq!(
+            tab.entries[0].environment(&owner()),
+            vec![
+                ("SHELL".to_owned(), "/bin/sh".to_owned()),
+                ("HOME".to_owned(), "/home/alice".to_owned()),
+                ("FOO".to_owned(), "bar".to_owned()),
+                ("PATH".to_owned(), "/usr/local/bin:/usr/bin".to_owned()),
+                ("LOGNAME".to_owned(), "alice".to_owned()),
+                ("USER".to_owned(), "alice".to_owned()),
+            ]
+        );
+    }
+
+    #[test]
+    fn find_jobs_by_kind() {
+        let tab = Crontab::parse("* * * * * wild\n30 4 * * * fixed\n@reboot boot").unwrap();
+        let moment = Moment::at(4 * 60 + 30);
+        let all: Vec<&str> = tab.find_jobs(&moment, true, true).iter().map(|e| e.command()).collect();
+        assert_eq!(all, vec!["wild", "fixed"]);
+        let wild: Vec<&str> = tab.find_jobs(&moment, true, false).iter().map(|e| e.command()).collect();
+        assert_eq!(wild, vec!["wild"]);
+        let reboot: Vec<&str> = tab.reboot_jobs().iter().map(|e| e.command()).collect();
+        assert_eq!(reboot, vec!["boot"]);
     }
 
     #[test]
@@ -246,4 +952,97 @@ mod tests {
     fn parse_comment_garbage() {
         assert_parses_to_exactly!(parse_comment("# trash here"), " trash here")
     }
+
+    #[test]
+    fn parse_env_keeps_lone_quotes() {
+        assert_parses_to_exactly!(
+            parse_env("QUOTE='"),
+            CrontabLine::Env("QUOTE".to_string(), "'".to_string())
+        )
+    }
+
+    #[test]
+    fn displays_canonically() {
+        let input = "# nightly\n\
+                     MAILTO = ops\n\
+                     CRONDY_TIMEOUT=2h\n\
+                     @daily root backup --all%first%second\n\
+                     PATH=\" /usr/local/bin:/usr/bin\"\n\
+                     CRONDY_TIMEOUT=none\n\
+                     */15 9-17 * jan-mar mon,fri root echo 100\\% done\\n\n";
+        let tab = Crontab::parse_as(input, Syntax::System).unwrap();
+        assert_eq!(
+            tab.to_string(),
+            "MAILTO=ops\n\
+             CRONDY_TIMEOUT=7200s\n\
+             0 0 * * * root backup --all%first%second\n\
+             PATH=\" /usr/local/bin:/usr/bin\"\n\
+             CRONDY_TIMEOUT=none\n\
+             */15 9-17 * 1-3 1,5 root echo 100\\% done\\n\n"
+        );
+        let tab = Crontab::parse("@reboot cat%one%%\n").unwrap();
+        assert_eq!(tab.entries[0].stdin(), Some("one\n\n"));
+        assert_eq!(tab.to_string(), "@reboot cat%one%%\n");
+    }
+
+    /// The parts of an entry that say what it does, rather than where it is.
+    fn meaning(entry: &Entry) -> impl PartialEq + std::fmt::Debug + '_ {
+        (&entry.schedule, &entry.user, &entry.cmd, &entry.stdin, &entry.envp, &entry.options)
+    }
+
+    #[derive(Debug, Clone)]
+    struct Lines(String);
+
+    /*
+     * Lines of a crontab made up of characters that mean something to the
+     * parser, so that whatever it makes of them gets tried.
+     */
+    impl Arbitrary for Lines {
+        fn arbitrary<G: Gen>(g: &mut G) -> Self {
+            fn pick<G: Gen>(g: &mut G, from: &str, count: usize) -> String {
+                let from: Vec<char> = from.chars().collect();
+                (0..count).map(|_| from[below(g, from.len())]).collect()
+            }
+            const SETTINGS: [&str; 5] = [
+                "CRONDY_OVERLAP=skip",
+                "CRONDY_TIMEOUT=1h",
+                "CRONDY_RETRIES=2",
+                "CRONDY_RETRY_BACKOFF=1.25",
+                "CRONDY_OVERLAP=allow",
+            ];
+            let mut text = String::new();
+            for _ in 0..below(g, 8) {
+                let line = match below(g, 4) {
+                    0 => {
+                        let (name_count, count) = (1 + below(g, 3), below(g, 5));
+                        let name = pick(g, "AB_", name_count);
+                        let value = pick(g, "a '\"=%\\ ", count);
+                        if bool::arbitrary(g) {
+                            format!("{}=\"{}\"", name, value)
+                        } else {
+                            format!("{}={}", name, value)
+                        }
+                    }
+                    1 => SETTINGS[below(g, SETTINGS.len())].to_owned(),
+                    _ => {
+                        let count = 1 + below(g, 8);
+                        format!("{} {}", Schedule::arbitrary(g), pick(g, "ab %\\'\" ", count))
+                    }
+                };
+                text.push_str(&line);
+                text.push('\n');
+            }
+            Lines(text)
+        }
+    }
+
+    quickcheck! {
+        fn display_parses_back(lines: Lines) -> bool {
+            let tab = Crontab::parse(&lines.0).unwrap();
+            let printed = tab.to_string();
+            let again = Crontab::parse(&printed).unwrap();
+            again.to_string() == printed
+                && again.entries.iter().map(meaning).eq(tab.entries.iter().map(meaning))
+        }
+    }
 }
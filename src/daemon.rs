@@ -0,0 +1,88 @@
+use std::ffi::CString;
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::os::unix::io::FromRawFd;
+use std::process;
+
+use failure::{bail, format_err, Error};
+
+/*
+ * Going into the background the classic way: fork, setsid to leave the
+ * controlling terminal behind, and fork again so that we can never pick one
+ * up, then chdir to / so we don't hold any filesystem busy and set our own
+ * umask rather than whatever we were started with.
+ *
+ * The process that was started stays in the foreground until the daemon
+ * says it is ready over a pipe, and exits 0 then. If the daemon exits
+ * before getting that far the pipe closes without a word and the launcher
+ * exits 1, so a broken crontab is still noticed by whoever started us.
+ */
+pub struct Daemon {
+    ready: File,
+}
+
+/// Fork into the background. Only the daemon returns.
+pub fn detach() -> Result<Daemon, Error> {
+    let mut fds = [0; 2];
+    // close-on-exec, or jobs started before we are ready would hold it open
+    if unsafe { libc::pipe2(fds.as_mut_ptr(), libc::O_CLOEXEC) } != 0 {
+        bail!("could not make a pipe: {}", io::Error::last_os_error());
+    }
+    let (mut waiting, ready) = unsafe { (File::from_raw_fd(fds[0]), File::from_raw_fd(fds[1])) };
+
+    if fork()? != 0 {
+        drop(ready);
+        let mut byte = [0];
+        process::exit(match waiting.read(&mut byte) {
+            Ok(1) => 0,
+            _ => 1,
+        });
+    }
+    drop(waiting);
+    if unsafe { libc::setsid() } < 0 {
+        bail!("could not start a new session: {}", io::Error::last_os_error());
+    }
+    if fork()? != 0 {
+        unsafe { libc::_exit(0) }
+    }
+
+    let root = CString::new("/").unwrap();
+    if unsafe { libc::chdir(root.as_ptr()) } != 0 {
+        bail!("could not change directory to /: {}", io::Error::last_os_error());
+    }
+    unsafe {
+        libc::umask(0o022);
+    }
+    Ok(Daemon { ready })
+}
+
+impl Daemon {
+    /// Let go of the terminal and tell the launcher we're up.
+    pub fn ready(mut self) -> Result<(), Error> {
+        let null = CString::new("/dev/null").unwrap();
+        let fd = unsafe { libc::open(null.as_ptr(), libc::O_RDWR) };
+        if fd < 0 {
+            bail!("could not open /dev/null: {}", io::Error::last_os_error());
+        }
+        for target in 0..3 {
+            if unsafe { libc::dup2(fd, target) } < 0 {
+                bail!("could not redirect fd {}: {}", target, io::Error::last_os_error());
+            }
+        }
+        if fd > 2 {
+            unsafe {
+                libc::close(fd);
+            }
+        }
+        self.ready
+            .write_all(&[0])
+            .map_err(|error| format_err!("could not tell the launcher we're up: {}", error))
+    }
+}
+
+fn fork() -> Result<libc::pid_t, Error> {
+    match unsafe { libc::fork() } {
+        -1 => bail!("could not fork: {}", io::Error::last_os_error()),
+        pid => Ok(pid),
+    }
+}
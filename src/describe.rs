@@ -0,0 +1,335 @@
+use crate::schedule::{Field, Schedule, When};
+
+const MONTHS: [&str; 12] = [
+    "January",
+    "February",
+    "March",
+    "April",
+    "May",
+    "June",
+    "July",
+    "August",
+    "September",
+    "October",
+    "November",
+    "December",
+];
+const DAYS_OF_WEEK: [&str; 8] = [
+    "Sunday",
+    "Monday",
+    "Tuesday",
+    "Wednesday",
+    "Thursday",
+    "Friday",
+    "Saturday",
+    "Sunday",
+];
+
+/// Up to this many times of day are listed rather than described.
+const MAX_TIMES: usize = 6;
+
+impl Schedule {
+    /// The schedule in English, like "every 15 minutes between 09:00 and
+    /// 17:59, Monday through Friday".
+    pub fn describe(&self) -> String {
+        match self {
+            Schedule::Reboot => "when crondy starts".to_owned(),
+            Schedule::When(when) => describe(when),
+        }
+    }
+}
+
+/*
+ * The time of day comes first, then the days and then the months, each left
+ * out when it is any at all. A handful of times of day are simply listed.
+ */
+fn describe(when: &When) -> String {
+    let days = days(when);
+    let months = months(&when.month);
+    let mut parts = vec![match times_of_day(&when.minute, &when.hour) {
+        Some(times) if days.is_none() && months.is_none() => format!("at {} every day", times),
+        Some(times) => format!("at {}", times),
+        None => time(&when.minute, &when.hour),
+    }];
+    parts.extend(days);
+    parts.extend(months);
+    parts.join(", ")
+}
+
+fn times_of_day(minute: &Field, hour: &Field) -> Option<String> {
+    let (minutes, hours) = (values(minute)?, values(hour)?);
+    if minutes.len() * hours.len() > MAX_TIMES {
+        return None;
+    }
+    let times = hours
+        .iter()
+        .flat_map(|hour| minutes.iter().map(move |minute| format!("{:02}:{:02}", hour, minute)))
+        .collect();
+    Some(and_list(times))
+}
+
+/// The values of a field that is nothing but values, in order.
+fn values(field: &Field) -> Option<Vec<usize>> {
+    let mut values = match field {
+        Field::Value(value) => vec![*value],
+        Field::List(items) => items
+            .iter()
+            .map(|item| match item {
+                Field::Value(value) => Some(*value),
+                _ => None,
+            })
+            .collect::<Option<_>>()?,
+        _ => return None,
+    };
+    values.sort_unstable();
+    values.dedup();
+    Some(values)
+}
+
+fn time(minute: &Field, hour: &Field) -> String {
+    let stepped = matches!(hour, Field::Star(Some(_)) | Field::Range(_, _, Some(_)));
+    let hours = match hour {
+        Field::Star(None) => None,
+        Field::Star(Some(step)) => Some(every(*step, "hour", "hours")),
+        Field::Value(hour) => Some(format!("between {:02}:00 and {:02}:59", hour, hour)),
+        Field::Range(start, end, None) => {
+            Some(format!("between {:02}:00 and {:02}:59", start, end))
+        }
+        Field::Range(start, end, Some(step)) => Some(format!(
+            "{} between {:02}:00 and {:02}:59",
+            every(*step, "hour", "hours"),
+            start,
+            end
+        )),
+        Field::List(_) => Some(format!("during hours {}", item(hour, &|hour| hour.to_string()))),
+    };
+    let minutes = match minute {
+        Field::Value(0) if stepped => return hours.unwrap_or_default(),
+        Field::Value(0) => "every hour".to_owned(),
+        Field::Value(minute) => format!("at {} past the hour", count(*minute, "minute", "minutes")),
+        Field::Star(None) => "every minute".to_owned(),
+        Field::Star(Some(step)) => every(*step, "minute", "minutes"),
+        Field::Range(start, end, step) => format!(
+            "{} from {} through {} past the hour",
+            every(step.unwrap_or(1), "minute", "minutes"),
+            start,
+            end
+        ),
+        Field::List(_) => format!(
+            "at {} minutes past the hour",
+            item(minute, &|minute| minute.to_string())
+        ),
+    };
+    match hours {
+        None => minutes,
+        Some(hours) if stepped => format!("{}, {}", minutes, hours),
+        Some(hours) => format!("{} {}", minutes, hours),
+    }
+}
+
+/*
+ * If either day field is a star, a day has to match both, as it would if it
+ * was left out. If neither is, matching one of them is enough.
+ */
+fn days(when: &When) -> Option<String> {
+    let day_of_month = match &when.day_of_month {
+        Field::Star(None) => None,
+        Field::Star(Some(step)) => Some(format!("on every {} day of the month", ordinal(*step))),
+        Field::Range(start, end, Some(step)) => Some(format!(
+            "on every {} day from the {} through the {} of the month",
+            ordinal(*step),
+            ordinal(*start),
+            ordinal(*end)
+        )),
+        field => Some(format!("on the {} of the month", item(field, &ordinal))),
+    };
+    let day_of_week = match &when.day_of_week {
+        Field::Star(None) => None,
+        Field::Star(Some(step)) => {
+            Some(format!("on every {} day of the week from Sunday", ordinal(*step)))
+        }
+        Field::Range(start, end, None) => {
+            Some(format!("{} through {}", day_of_week(*start), day_of_week(*end)))
+        }
+        Field::Range(start, end, Some(step)) => Some(format!(
+            "on every {} day from {} through {}",
+            ordinal(*step),
+            day_of_week(*start),
+            day_of_week(*end)
+        )),
+        field => Some(format!("on {}", item(field, &day_of_week))),
+    };
+    match (day_of_month, day_of_week) {
+        (Some(day_of_month), Some(day_of_week))
+            if !when.day_of_month.is_star() && !when.day_of_week.is_star() =>
+        {
+            let on = if day_of_week.starts_with("on ") { "" } else { "on " };
+            Some(format!("{} or {}{}", day_of_month, on, day_of_week))
+        }
+        (Some(day_of_month), Some(day_of_week)) => {
+            Some(format!("{}, {}", day_of_month, day_of_week))
+        }
+        (day_of_month, day_of_week) => day_of_month.or(day_of_week),
+    }
+}
+
+fn months(month: &Field) -> Option<String> {
+    match month {
+        Field::Star(None) => None,
+        Field::Star(Some(step)) => Some(format!("in every {} month from January", ordinal(*step))),
+        Field::Range(start, end, None) => {
+            Some(format!("from {} through {}", month_name(*start), month_name(*end)))
+        }
+        Field::Range(start, end, Some(step)) => Some(format!(
+            "in every {} month from {} through {}",
+            ordinal(*step),
+            month_name(*start),
+            month_name(*end)
+        )),
+        field => Some(format!("in {}", item(field, &month_name))),
+    }
+}
+
+/// A field, or an item of a list, with its values named by `name`.
+fn item(field: &Field, name: &dyn Fn(usize) -> String) -> String {
+    match field {
+        Field::Value(value) => name(*value),
+        Field::Range(start, end, None) => format!("{} through {}", name(*start), name(*end)),
+        Field::Range(start, end, Some(step)) => format!(
+            "every {} from {} through {}",
+            ordinal(*step),
+            name(*start),
+            name(*end)
+        ),
+        Field::List(items) => and_list(items.iter().map(|field| item(field, name)).collect()),
+        Field::Star(None) => "any".to_owned(),
+        Field::Star(Some(step)) => format!("every {}", ordinal(*step)),
+    }
+}
+
+fn month_name(month: usize) -> String {
+    match MONTHS.get(month.wrapping_sub(1)) {
+        Some(name) => (*name).to_owned(),
+        None => format!("month {}", month),
+    }
+}
+
+fn day_of_week(day: usize) -> String {
+    match DAYS_OF_WEEK.get(day) {
+        Some(name) => (*name).to_owned(),
+        None => format!("day {} of the week", day),
+    }
+}
+
+fn ordinal(number: usize) -> String {
+    let suffix = match (number % 10, number % 100) {
+        (_, 11..=13) => "th",
+        (1, _) => "st",
+        (2, _) => "nd",
+        (3, _) => "rd",
+        _ => "th",
+    };
+    format!("{}{}", number, suffix)
+}
+
+fn count(number: usize, one: &str, many: &str) -> String {
+    format!("{} {}", number, if number == 1 { one } else { many })
+}
+
+fn every(step: usize, one: &str, many: &str) -> String {
+    if step == 1 {
+        format!("every {}", one)
+    } else {
+        format!("every {} {}", step, many)
+    }
+}
+
+/// "a", "a and b", "a, b and c".
+fn and_list(mut items: Vec<String>) -> String {
+    match items.pop() {
+        Some(last) if !items.is_empty() => format!("{} and {}", items.join(", "), last),
+        Some(last) => last,
+        None => String::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::Schedule;
+    use quickcheck::quickcheck;
+
+    fn describe(schedule: &str) -> String {
+        let (_, schedule) = Schedule::parse(&format!("{} ", schedule)).unwrap();
+        schedule.describe()
+    }
+
+    #[test]
+    fn describes_nicknames() {
+        assert_eq!(describe("@reboot"), "when crondy starts");
+        assert_eq!(describe("@hourly"), "every hour");
+        assert_eq!(describe("@daily"), "at 00:00 every day");
+        assert_eq!(describe("@weekly"), "at 00:00, on Sunday");
+        assert_eq!(describe("@monthly"), "at 00:00, on the 1st of the month");
+        assert_eq!(describe("@yearly"), "at 00:00, on the 1st of the month, in January");
+    }
+
+    #[test]
+    fn describes_times_of_day() {
+        assert_eq!(describe("* * * * *"), "every minute");
+        assert_eq!(
+            describe("*/15 9-17 * * 1-5"),
+            "every 15 minutes between 09:00 and 17:59, Monday through Friday"
+        );
+        assert_eq!(describe("30 17,9 * * *"), "at 09:30 and 17:30 every day");
+        assert_eq!(describe("0 */2 * * *"), "every 2 hours");
+        assert_eq!(describe("0 9-17/4 * * *"), "every 4 hours between 09:00 and 17:59");
+        assert_eq!(describe("0 9-17 * * *"), "every hour between 09:00 and 17:59");
+        assert_eq!(describe("1 * * * *"), "at 1 minute past the hour");
+        assert_eq!(describe("5-10/5 * * * *"), "every 5 minutes from 5 through 10 past the hour");
+        assert_eq!(
+            describe("0,30 22-2 * * *"),
+            "at 0 and 30 minutes past the hour between 22:00 and 02:59"
+        );
+        assert_eq!(
+            describe("45 2,8-12,*/6 * * *"),
+            "at 45 minutes past the hour during hours 2, 8 through 12 and every 6th"
+        );
+    }
+
+    #[test]
+    fn describes_days_and_months() {
+        assert_eq!(
+            describe("0 12 * jan-mar,DEC sat,sun"),
+            "at 12:00, on Saturday and Sunday, in January through March and December"
+        );
+        assert_eq!(describe("0 6 */2 */3 *"), "at 06:00, on every 2nd day of the month, \
+                                               in every 3rd month from January");
+        assert_eq!(describe("0 6 1-21/7 4-10 *"), "at 06:00, on every 7th day from the 1st \
+                                                   through the 21st of the month, \
+                                                   from April through October");
+        assert_eq!(describe("0 0 * * 0-6/2"), "at 00:00, on every 2nd day from Sunday \
+                                               through Saturday");
+    }
+
+    #[test]
+    fn describes_either_day_rule() {
+        assert_eq!(
+            describe("0 0 1,15 * 5"),
+            "at 00:00, on the 1st and 15th of the month or on Friday"
+        );
+        assert_eq!(
+            describe("0 0 13 * 1-5"),
+            "at 00:00, on the 13th of the month or on Monday through Friday"
+        );
+        assert_eq!(
+            describe("0 0 */2 * 1"),
+            "at 00:00, on every 2nd day of the month, on Monday"
+        );
+    }
+
+    quickcheck! {
+        fn describes_anything(schedule: Schedule) -> bool {
+            !schedule.describe().is_empty()
+        }
+    }
+}
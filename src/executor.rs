@@ -0,0 +1,804 @@
+use std::convert::TryFrom;
+use std::time::{Duration, Instant};
+
+use crondy::clock::{self, Minute};
+use crondy::crontab::Entry;
+use crondy::options::Overlap;
+use crondy::user::User;
+use log::{debug, error, Level};
+
+use crate::job::{self, Job, Outcome};
+use crate::logging::Event;
+use crate::metrics::Metrics;
+
+/// Starts jobs and keeps track of them until they exit.
+pub struct Executor {
+    owner: User,
+    limits: Limits,
+    running: Vec<Running>,
+    pending: Vec<Pending>,
+    retries: Vec<Retry>,
+    enqueued: u64, // runs queued so far, to keep the queue FIFO
+    metrics: Metrics,
+    stopping: bool, // see stop_starting
+}
+
+/// How many jobs may run at once.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Limits {
+    pub max_jobs: Option<usize>,
+}
+
+struct Running {
+    entry: Entry,
+    scheduled: Minute,
+    attempt: u32,
+    process: Job,
+    started: Instant,
+    started_at: i64, // on crondy's clock, in seconds
+    timeout: Option<Duration>,
+    kill_grace: Duration,
+    terminated: Option<Instant>, // when the timeout ran out
+    killed: Option<Instant>,     // when the grace period did
+}
+
+struct Pending {
+    entry: Entry,
+    scheduled: Minute,
+    attempt: u32,
+    number: u64,
+    after_previous: bool, // queued by CRONDY_OVERLAP=queue
+}
+
+struct Retry {
+    entry: Entry,
+    scheduled: Minute,
+    attempt: u32,
+    due: Instant,
+}
+
+/// A run that is going on, as far as anyone outside the executor cares.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RunningJob {
+    pub pid: u32,
+    pub location: String, // see Entry::location
+    pub command: String,
+    pub attempt: u32,
+    pub started: i64, // on crondy's clock, in seconds
+}
+
+/// A run that has come to an end. Times are on crondy's clock (see
+/// clock.rs), `output` is the number of bytes written to stdout and stderr.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Finished {
+    pub line: usize,
+    pub source: String,
+    pub pid: u32,
+    pub scheduled: Minute,
+    pub attempt: u32,
+    pub started: i64,
+    pub ended: i64,
+    pub outcome: Outcome,
+    pub output: u64,
+}
+
+impl Executor {
+    pub fn new(owner: User, limits: Limits) -> Self {
+        Executor {
+            owner,
+            limits,
+            running: Vec::new(),
+            pending: Vec::new(),
+            retries: Vec::new(),
+            enqueued: 0,
+            metrics: Metrics::default(),
+            stopping: false,
+        }
+    }
+
+    /// What has been counted about the runs so far.
+    pub fn metrics(&self) -> &Metrics {
+        &self.metrics
+    }
+
+    /// Queue a run of `entry` that was due at `scheduled`, unless its
+    /// overlap policy says otherwise. Nothing gets started until the next
+    /// `run_queue`.
+    pub fn run(&mut self, entry: &Entry, scheduled: Minute) {
+        let previous = self.running(entry);
+        let waiting = self.pending.iter().any(|p| p.entry.is_same(entry));
+        let mut after_previous = false;
+        let event = |kind| Event {
+            kind,
+            entry: Some(entry),
+            scheduled: Some(scheduled),
+            ..Event::default()
+        };
+        if !previous.is_empty() || waiting {
+            match entry.options().overlap {
+                Overlap::Allow => (),
+                Overlap::Skip => {
+                    event!(
+                        Level::Info,
+                        event("job_skipped"),
+                        "skipping {} ({}): still running as {:?}{}",
+                        entry.location(),
+                        entry.command(),
+                        previous,
+                        if waiting { " or waiting to run" } else { "" }
+                    );
+                    self.metrics.missed(entry);
+                    return;
+                }
+                Overlap::Queue if waiting => {
+                    event!(
+                        Level::Info,
+                        event("job_skipped"),
+                        "skipping {} ({}): still running as {:?} and already queued",
+                        entry.location(),
+                        entry.command(),
+                        previous
+                    );
+                    self.metrics.missed(entry);
+                    return;
+                }
+                Overlap::Queue => {
+                    event!(
+                        Level::Info,
+                        event("job_queued"),
+                        "queueing {} ({}): still running as {:?}",
+                        entry.location(),
+                        entry.command(),
+                        previous
+                    );
+                    after_previous = true;
+                }
+                Overlap::Kill => {
+                    event!(
+                        Level::Info,
+                        event("job_killed"),
+                        "killing {:?} to start {} ({}) again",
+                        previous,
+                        entry.location(),
+                        entry.command()
+                    );
+                    for pid in previous {
+                        job::signal(pid, libc::SIGTERM);
+                    }
+                }
+            }
+        }
+        self.enqueue(entry.clone(), scheduled, 1, after_previous);
+    }
+
+    fn enqueue(&mut self, entry: Entry, scheduled: Minute, attempt: u32, after_previous: bool) {
+        if self.stopping {
+            return;
+        }
+        self.enqueued += 1;
+        self.pending.push(Pending {
+            entry,
+            scheduled,
+            attempt,
+            number: self.enqueued,
+            after_previous,
+        });
+    }
+
+    /*
+     * Start queued runs for as long as the limits allow, highest
+     * CRONDY_PRIORITY first and otherwise in the order they were queued.
+     * Returns whether anything is left waiting.
+     */
+    pub fn run_queue(&mut self) -> bool {
+        loop {
+            if let Some(max) = self.limits.max_jobs {
+                if self.running.len() >= max {
+                    break;
+                }
+            }
+            let running = &self.running;
+            let next = self
+                .pending
+                .iter()
+                .enumerate()
+                .filter(|(_, p)| {
+                    !(p.after_previous && running.iter().any(|r| r.entry.is_same(&p.entry)))
+                })
+                .max_by_key(|(_, p)| (p.entry.options().priority, std::cmp::Reverse(p.number)))
+                .map(|(index, _)| index);
+            match next {
+                Some(index) => {
+                    let pending = self.pending.remove(index);
+                    self.start(pending.entry, pending.scheduled, pending.attempt);
+                }
+                None => break,
+            }
+        }
+        if !self.pending.is_empty() {
+            debug!("{} runs waiting in the queue", self.pending.len());
+        }
+        !self.pending.is_empty()
+    }
+
+    /// Collect jobs that have exited, schedule retries of the ones that
+    /// failed and start whatever was waiting for them.
+    pub fn reap(&mut self) -> Vec<Finished> {
+        let mut finished = Vec::new();
+        let mut failed = Vec::new();
+        self.running.retain_mut(|job| {
+            let pid = job.process.child.id();
+            let line = job.entry.line();
+            let location = job.entry.location();
+            match job.process.child.try_wait() {
+                Ok(Some(status)) => {
+                    let outcome = if job.terminated.is_some() {
+                        Outcome::TimedOut
+                    } else {
+                        Outcome::from_status(status)
+                    };
+                    let ended = clock::now_seconds();
+                    let event = Event {
+                        kind: "job_finished",
+                        entry: Some(&job.entry),
+                        scheduled: Some(job.scheduled),
+                        pid: Some(pid),
+                        attempt: Some(job.attempt),
+                        outcome: Some(outcome),
+                        duration: Some(ended - job.started_at),
+                    };
+                    if outcome == Outcome::TimedOut {
+                        event!(
+                            Level::Warn,
+                            event,
+                            "job {} for {} timed out ({})",
+                            pid,
+                            location,
+                            status
+                        );
+                    } else {
+                        event!(Level::Info, event, "job {} for {} {}", pid, location, outcome);
+                    }
+                    if outcome != Outcome::Exited(0) {
+                        failed.push((job.entry.clone(), job.scheduled, job.attempt));
+                    }
+                    finished.push(Finished {
+                        line,
+                        source: job.entry.source().to_owned(),
+                        pid,
+                        scheduled: job.scheduled,
+                        attempt: job.attempt,
+                        started: job.started_at,
+                        ended,
+                        outcome,
+                        output: job.process.output_size(),
+                    });
+                    false
+                }
+                Ok(None) => true,
+                Err(error) => {
+                    error!("could not check on job {}: {}", pid, error);
+                    false
+                }
+            }
+        });
+        for run in &finished {
+            self.metrics.finished(run);
+        }
+        for (entry, scheduled, attempt) in failed {
+            self.schedule_retry(entry, scheduled, attempt);
+        }
+        if !finished.is_empty() {
+            self.run_queue();
+        }
+        finished
+    }
+
+    /*
+     * The n-th retry waits CRONDY_RETRY_DELAY * CRONDY_RETRY_BACKOFF^(n-1).
+     * A retry that would not start before the entry is next due anyway is
+     * dropped, so that retries and regular runs never pile up. So is one
+     * that would wait longer than we can count, which is past any next run.
+     */
+    fn schedule_retry(&mut self, entry: Entry, scheduled: Minute, attempt: u32) {
+        let options = entry.options();
+        if attempt > options.retries || self.stopping {
+            return;
+        }
+        let now = clock::now_seconds();
+        let event = |kind| Event {
+            kind,
+            entry: Some(&entry),
+            scheduled: Some(scheduled),
+            attempt: Some(attempt + 1),
+            ..Event::default()
+        };
+        let delay = Duration::try_from_secs_f64(
+            options.retry_delay.as_secs_f64() * options.retry_backoff.powi(attempt as i32 - 1),
+        )
+        .ok();
+        let starts = delay.and_then(|delay| {
+            let due = Instant::now().checked_add(delay)?;
+            Some((delay, due, now.checked_add(i64::try_from(delay.as_secs()).ok()?)?))
+        });
+        let (delay, due, start) = match starts {
+            Some(starts) => starts,
+            None => {
+                event!(
+                    Level::Info,
+                    event("retry_dropped"),
+                    "not retrying {} ({}): retry {} would wait too long",
+                    entry.location(),
+                    entry.command(),
+                    attempt
+                );
+                return;
+            }
+        };
+        if let Some(next) = entry.schedule().next_after(now.div_euclid(60)) {
+            if start >= next * 60 {
+                event!(
+                    Level::Info,
+                    event("retry_dropped"),
+                    "not retrying {} ({}): it is due again before retry {} would start",
+                    entry.location(),
+                    entry.command(),
+                    attempt
+                );
+                return;
+            }
+        }
+        event!(
+            Level::Info,
+            event("retry_scheduled"),
+            "retrying {} ({}) in {}s, retry {} of {}",
+            entry.location(),
+            entry.command(),
+            delay.as_secs(),
+            attempt,
+            options.retries
+        );
+        self.retries.push(Retry {
+            entry,
+            scheduled,
+            attempt: attempt + 1,
+            due,
+        });
+    }
+
+    /// Queue the retries that are due.
+    pub fn run_due_retries(&mut self) {
+        let now = Instant::now();
+        let (due, later) = self.retries.drain(..).partition(|r| r.due <= now);
+        self.retries = later;
+        for retry in due {
+            self.enqueue(retry.entry, retry.scheduled, retry.attempt, false);
+        }
+    }
+
+    /*
+     * A job that runs past its timeout gets SIGTERM sent to its process
+     * group, and a single SIGKILL once its grace period is over. It is
+     * reported as timed out whatever its exit status turns out to be.
+     */
+    pub fn enforce_timeouts(&mut self) {
+        let now = Instant::now();
+        for job in self.running.iter_mut() {
+            let pid = job.process.child.id();
+            let location = job.entry.location();
+            let event = |kind| Event {
+                kind,
+                entry: Some(&job.entry),
+                scheduled: Some(job.scheduled),
+                pid: Some(pid),
+                attempt: Some(job.attempt),
+                ..Event::default()
+            };
+            match (job.timeout, job.terminated) {
+                (Some(timeout), None) if now.duration_since(job.started) >= timeout => {
+                    event!(
+                        Level::Warn,
+                        event("job_timed_out"),
+                        "job {} for {} ran longer than {}s, terminating it",
+                        pid,
+                        location,
+                        timeout.as_secs()
+                    );
+                    job::signal(pid, libc::SIGTERM);
+                    job.terminated = Some(now);
+                }
+                (_, Some(terminated))
+                    if job.killed.is_none() && now.duration_since(terminated) >= job.kill_grace =>
+                {
+                    event!(
+                        Level::Warn,
+                        event("job_killed"),
+                        "job {} for {} still running {}s after SIGTERM, killing it",
+                        pid,
+                        location,
+                        job.kill_grace.as_secs()
+                    );
+                    job::signal(pid, libc::SIGKILL);
+                    job.killed = Some(now);
+                }
+                _ => (),
+            }
+        }
+    }
+
+    /// Forget the runs and retries waiting to start, and start no more.
+    /// Returns how many runs were waiting.
+    pub fn stop_starting(&mut self) -> usize {
+        let waiting = self.pending.len() + self.retries.len();
+        self.pending.clear();
+        self.retries.clear();
+        self.stopping = true;
+        waiting
+    }
+
+    /// Send `signal` to every running job's process group.
+    pub fn signal_all(&self, signal: libc::c_int) {
+        for job in &self.running {
+            job::signal(job.process.child.id(), signal);
+        }
+    }
+
+    /// The pids of the runs of `entry` that are still going.
+    pub fn running(&self, entry: &Entry) -> Vec<u32> {
+        self.running
+            .iter()
+            .filter(|job| job.entry.is_same(entry))
+            .map(|job| job.process.child.id())
+            .collect()
+    }
+
+    /// Every job that is running, oldest first.
+    pub fn jobs(&self) -> Vec<RunningJob> {
+        self.running
+            .iter()
+            .map(|job| RunningJob {
+                pid: job.process.child.id(),
+                location: job.entry.location(),
+                command: job.entry.command().to_owned(),
+                attempt: job.attempt,
+                started: job.started_at,
+            })
+            .collect()
+    }
+
+    fn start(&mut self, entry: Entry, scheduled: Minute, attempt: u32) {
+        match job::spawn(&entry, &self.owner, attempt) {
+            Ok(process) => {
+                event!(
+                    Level::Info,
+                    Event {
+                        kind: "job_started",
+                        entry: Some(&entry),
+                        scheduled: Some(scheduled),
+                        pid: Some(process.child.id()),
+                        attempt: Some(attempt),
+                        ..Event::default()
+                    },
+                    "({}) CMD ({})",
+                    self.owner.name,
+                    entry.command()
+                );
+                let started_at = clock::now_seconds();
+                // retries are late on purpose
+                let lag = Some(started_at - scheduled * 60).filter(|_| attempt == 1);
+                self.metrics.started(&entry, lag);
+                self.running.push(Running {
+                    timeout: entry.options().timeout,
+                    kill_grace: entry.options().kill_grace,
+                    entry,
+                    scheduled,
+                    attempt,
+                    process,
+                    started: Instant::now(),
+                    started_at,
+                    terminated: None,
+                    killed: None,
+                });
+            }
+            Err(error) => event!(
+                Level::Error,
+                Event {
+                    kind: "job_not_started",
+                    entry: Some(&entry),
+                    scheduled: Some(scheduled),
+                    attempt: Some(attempt),
+                    ..Event::default()
+                },
+                "could not start {:?}: {}",
+                entry.command(),
+                error
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crondy::clock::Moment;
+    use crondy::crontab::Crontab;
+    use std::thread;
+    use std::time::Duration;
+
+    fn executor() -> Executor {
+        Executor::new(User::current().unwrap(), Limits::default())
+    }
+
+    fn run(executor: &mut Executor, entry: &Entry) {
+        executor.run(entry, 0);
+        executor.run_queue();
+    }
+
+    fn wait_for_all(executor: &mut Executor) -> Vec<Finished> {
+        let mut finished = Vec::new();
+        while !executor.running.is_empty()
+            || !executor.pending.is_empty()
+            || !executor.retries.is_empty()
+        {
+            thread::sleep(Duration::from_millis(10));
+            executor.enforce_timeouts();
+            finished.extend(executor.reap());
+            executor.run_due_retries();
+            executor.run_queue();
+        }
+        finished
+    }
+
+    #[test]
+    fn allows_overlap_by_default() {
+        let tab = Crontab::parse("@reboot sleep 1").unwrap();
+        let entry = tab.reboot_jobs()[0];
+        let mut executor = executor();
+        run(&mut executor, entry);
+        run(&mut executor, entry);
+        assert_eq!(executor.running(entry).len(), 2);
+        let jobs = executor.jobs();
+        assert_eq!(jobs.len(), 2);
+        assert_eq!((jobs[0].location.as_str(), jobs[0].command.as_str()), ("line 1", "sleep 1"));
+        wait_for_all(&mut executor);
+    }
+
+    #[test]
+    fn skips_while_running() {
+        let tab = Crontab::parse("CRONDY_OVERLAP=skip\n@reboot sleep 1").unwrap();
+        let entry = tab.reboot_jobs()[0];
+        let mut executor = executor();
+        run(&mut executor, entry);
+        let first = executor.running(entry);
+        run(&mut executor, entry);
+        assert_eq!(executor.running(entry), first);
+        assert!(executor.pending.is_empty());
+        wait_for_all(&mut executor);
+    }
+
+    #[test]
+    fn queues_one_run() {
+        let tab = Crontab::parse("CRONDY_OVERLAP=queue\n@reboot sleep 0.2").unwrap();
+        let entry = tab.reboot_jobs()[0];
+        let mut executor = executor();
+        run(&mut executor, entry);
+        let first = executor.running(entry);
+        run(&mut executor, entry);
+        run(&mut executor, entry);
+        assert_eq!(executor.running(entry), first);
+        assert_eq!(executor.pending.len(), 1);
+
+        while executor.running(entry) == first {
+            thread::sleep(Duration::from_millis(10));
+            executor.reap();
+        }
+        assert_eq!(executor.running(entry).len(), 1);
+        assert!(executor.pending.is_empty());
+        wait_for_all(&mut executor);
+    }
+
+    #[test]
+    fn kills_previous_run() {
+        let tab = Crontab::parse("CRONDY_OVERLAP=kill\n@reboot sleep 10").unwrap();
+        let entry = tab.reboot_jobs()[0];
+        let mut executor = executor();
+        run(&mut executor, entry);
+        let first = executor.running(entry);
+        run(&mut executor, entry);
+        let mut left = executor.running(entry);
+        while left.len() > 1 {
+            thread::sleep(Duration::from_millis(10));
+            executor.reap();
+            left = executor.running(entry);
+        }
+        assert_ne!(left, first);
+        job::signal(left[0], libc::SIGTERM);
+        wait_for_all(&mut executor);
+    }
+
+    #[test]
+    fn reports_outcomes() {
+        let tab = Crontab::parse("@reboot exit 3\n@reboot kill -9 $$").unwrap();
+        let mut executor = executor();
+        for entry in tab.reboot_jobs() {
+            run(&mut executor, entry);
+        }
+        let mut outcomes: Vec<(usize, Outcome)> = wait_for_all(&mut executor)
+            .into_iter()
+            .map(|f| (f.line, f.outcome))
+            .collect();
+        outcomes.sort_by_key(|(line, _)| *line);
+        assert_eq!(
+            outcomes,
+            vec![(1, Outcome::Exited(3)), (2, Outcome::Signaled(9))]
+        );
+    }
+
+    #[test]
+    fn terminates_after_timeout() {
+        let tab = Crontab::parse("CRONDY_TIMEOUT=1\n@reboot sleep 30; sleep 30").unwrap();
+        let mut executor = executor();
+        run(&mut executor, tab.reboot_jobs()[0]);
+        let started = Instant::now();
+        let finished = wait_for_all(&mut executor);
+        assert_eq!(finished[0].outcome, Outcome::TimedOut);
+        assert!(started.elapsed() < Duration::from_secs(10));
+    }
+
+    #[test]
+    fn kills_after_grace_period() {
+        let tab = Crontab::parse(
+            "CRONDY_TIMEOUT=1\nCRONDY_KILL_GRACE=1\n@reboot trap '' TERM; sleep 30; sleep 30",
+        )
+        .unwrap();
+        let mut executor = executor();
+        run(&mut executor, tab.reboot_jobs()[0]);
+        let started = Instant::now();
+        let finished = wait_for_all(&mut executor);
+        assert_eq!(finished[0].outcome, Outcome::TimedOut);
+        assert!(started.elapsed() >= Duration::from_secs(2));
+        assert!(started.elapsed() < Duration::from_secs(10));
+    }
+
+    #[test]
+    fn kills_only_once() {
+        let tab = Crontab::parse(
+            "CRONDY_TIMEOUT=1\nCRONDY_KILL_GRACE=0\n@reboot trap '' TERM; sleep 30; sleep 30",
+        )
+        .unwrap();
+        let mut executor = executor();
+        run(&mut executor, tab.reboot_jobs()[0]);
+        executor.running[0].started -= Duration::from_secs(2);
+        executor.enforce_timeouts();
+        assert!(executor.running[0].terminated.is_some());
+        executor.enforce_timeouts();
+        let killed = executor.running[0].killed;
+        assert!(killed.is_some());
+        thread::sleep(Duration::from_millis(10));
+        executor.enforce_timeouts();
+        assert_eq!(executor.running[0].killed, killed);
+        assert_eq!(wait_for_all(&mut executor)[0].outcome, Outcome::TimedOut);
+    }
+
+    fn started_lines(finished: Vec<Finished>) -> Vec<usize> {
+        finished.into_iter().map(|f| f.line).collect()
+    }
+
+    #[test]
+    fn limits_jobs_in_priority_then_fifo_order() {
+        let tab = Crontab::parse(
+            "@reboot true\n@reboot true\nCRONDY_PRIORITY=5\n@reboot true\n@reboot true",
+        )
+        .unwrap();
+        let mut executor = Executor::new(
+            User::current().unwrap(),
+            Limits { max_jobs: Some(1) },
+        );
+        for entry in tab.reboot_jobs() {
+            executor.run(entry, 0);
+        }
+        assert!(executor.run_queue());
+        assert_eq!(executor.running.len(), 1);
+        assert_eq!(executor.pending.len(), 3);
+
+        // only one at a time, so they finish in the order they started
+        assert_eq!(started_lines(wait_for_all(&mut executor)), vec![4, 5, 1, 2]);
+    }
+
+    #[test]
+    fn skips_while_waiting_in_queue() {
+        let tab = Crontab::parse("CRONDY_OVERLAP=skip\n@reboot true").unwrap();
+        let entry = tab.reboot_jobs()[0];
+        let mut executor = executor();
+        executor.run(entry, 0);
+        executor.run(entry, 0);
+        assert_eq!(executor.pending.len(), 1);
+        executor.run_queue();
+        wait_for_all(&mut executor);
+    }
+
+    #[test]
+    fn starts_nothing_more_once_stopping() {
+        let tab = Crontab::parse(
+            "CRONDY_RETRIES=1\nCRONDY_RETRY_DELAY=0\n@reboot false\n@reboot true",
+        )
+        .unwrap();
+        let mut executor = executor();
+        executor.limits.max_jobs = Some(1);
+        executor.run(tab.reboot_jobs()[0], 0);
+        executor.run(tab.reboot_jobs()[1], 0);
+        executor.run_queue();
+        assert_eq!(executor.stop_starting(), 1);
+        executor.run(tab.reboot_jobs()[1], 0);
+        let finished = wait_for_all(&mut executor);
+        assert_eq!(started_lines(finished), vec![3]);
+    }
+
+    #[test]
+    fn retries_failures_with_attempt_numbers() {
+        let tab = Crontab::parse(
+            "CRONDY_RETRIES=2\nCRONDY_RETRY_DELAY=0\n@reboot exit $CRONDY_ATTEMPT",
+        )
+        .unwrap();
+        let mut executor = executor();
+        run(&mut executor, tab.reboot_jobs()[0]);
+        let attempts: Vec<(u32, Outcome)> = wait_for_all(&mut executor)
+            .into_iter()
+            .map(|f| (f.attempt, f.outcome))
+            .collect();
+        assert_eq!(
+            attempts,
+            vec![
+                (1, Outcome::Exited(1)),
+                (2, Outcome::Exited(2)),
+                (3, Outcome::Exited(3))
+            ]
+        );
+    }
+
+    #[test]
+    fn stops_retrying_after_success() {
+        let tab = Crontab::parse(
+            "CRONDY_RETRIES=5\nCRONDY_RETRY_DELAY=0\n@reboot test $CRONDY_ATTEMPT -ge 2",
+        )
+        .unwrap();
+        let mut executor = executor();
+        run(&mut executor, tab.reboot_jobs()[0]);
+        assert_eq!(wait_for_all(&mut executor).len(), 2);
+    }
+
+    #[test]
+    fn backs_off_exponentially() {
+        let tab = Crontab::parse(
+            "CRONDY_RETRIES=3\nCRONDY_RETRY_DELAY=1m\nCRONDY_RETRY_BACKOFF=3\n@reboot false",
+        )
+        .unwrap();
+        let entry = tab.reboot_jobs()[0].clone();
+        let mut executor = executor();
+        let before = Instant::now();
+        executor.schedule_retry(entry.clone(), 0, 3);
+        let delay = executor.retries[0].due - before;
+        assert!(delay >= Duration::from_secs(9 * 60) && delay < Duration::from_secs(9 * 60 + 5));
+        executor.schedule_retry(entry, 0, 4);
+        assert_eq!(executor.retries.len(), 1);
+    }
+
+    #[test]
+    fn drops_retries_that_would_wait_too_long() {
+        let tab = Crontab::parse(
+            "CRONDY_RETRIES=30\nCRONDY_RETRY_DELAY=1m\nCRONDY_RETRY_BACKOFF=10\n@reboot false",
+        )
+        .unwrap();
+        let mut executor = executor();
+        executor.schedule_retry(tab.reboot_jobs()[0].clone(), 0, 30);
+        assert!(executor.retries.is_empty());
+    }
+
+    #[test]
+    fn does_not_retry_past_the_next_run() {
+        let tab = Crontab::parse("CRONDY_RETRIES=1\nCRONDY_RETRY_DELAY=2m\n* * * * * false").unwrap();
+        let mut executor = executor();
+        executor.schedule_retry(tab.find_jobs(&Moment::at(0), true, true)[0].clone(), 0, 1);
+        assert!(executor.retries.is_empty());
+    }
+}
@@ -0,0 +1,393 @@
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use crondy::clock::{self, Moment};
+use failure::{bail, format_err, Error};
+use log::warn;
+
+use crate::executor::Finished;
+use crate::job::Outcome;
+
+/// Start a new history file once the current one is this big.
+pub const MAX_SIZE: u64 = 1 << 20;
+/// How many old history files to keep around.
+pub const KEEP: usize = 3;
+
+/*
+ * Every run that came to an end, one line per run, oldest first:
+ *
+ *     <scheduled> <started> <ended> <pid> <attempt> <outcome> <output> <line> <entry>
+ *
+ * <scheduled> is the minute the run was due, <started> and <ended> are in
+ * seconds, all on crondy's clock (see clock.rs). <outcome> is `exit=N`,
+ * `signal=N` or `timeout`, <output> the bytes the job wrote, <line> where
+ * the entry was in the crontab and <entry> the entry as written there. When
+ * the file grows past MAX_SIZE it moves to `<path>.1`, that one to `<path>.2`
+ * and so on, and the oldest is dropped.
+ */
+#[derive(Debug)]
+pub struct History {
+    path: PathBuf,
+    max_size: u64,
+    keep: usize,
+}
+
+impl History {
+    pub fn new(path: &Path) -> Self {
+        History {
+            path: path.to_owned(),
+            max_size: MAX_SIZE,
+            keep: KEEP,
+        }
+    }
+
+    pub fn append(&self, run: &Finished) -> Result<(), Error> {
+        self.write(run)
+            .map_err(|error| format_err!("could not write {}: {}", self.path.display(), error))
+    }
+
+    fn write(&self, run: &Finished) -> io::Result<()> {
+        match fs::metadata(&self.path) {
+            Ok(metadata) if metadata.len() >= self.max_size => self.rotate()?,
+            _ => (),
+        }
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        // one write per line, so that lines never end up interleaved
+        file.write_all(format_run(run).as_bytes())
+    }
+
+    fn rotate(&self) -> io::Result<()> {
+        for n in (1..self.keep).rev() {
+            match fs::rename(self.rotated(n), self.rotated(n + 1)) {
+                Err(error) if error.kind() != io::ErrorKind::NotFound => return Err(error),
+                _ => (),
+            }
+        }
+        if self.keep == 0 {
+            fs::remove_file(&self.path)
+        } else {
+            fs::rename(&self.path, self.rotated(1))
+        }
+    }
+
+    fn rotated(&self, n: usize) -> PathBuf {
+        let mut name = self.path.clone().into_os_string();
+        name.push(format!(".{}", n));
+        PathBuf::from(name)
+    }
+
+    /// Every run on record, rotated files included, oldest first. Lines
+    /// that aren't runs, like one cut short by a crash, are skipped.
+    pub fn read(&self) -> Result<Vec<Finished>, Error> {
+        let mut runs = Vec::new();
+        let paths = (1..=self.keep)
+            .rev()
+            .map(|n| self.rotated(n))
+            .chain(Some(self.path.clone()));
+        for path in paths {
+            let contents = match fs::read_to_string(&path) {
+                Ok(contents) => contents,
+                Err(ref error) if error.kind() == io::ErrorKind::NotFound => continue,
+                Err(error) => bail!("could not read {}: {}", path.display(), error),
+            };
+            for (number, line) in contents.lines().enumerate() {
+                match parse_run(line) {
+                    Some(run) => runs.push(run),
+                    None => warn!("{}:{}: not a run, skipping it", path.display(), number + 1),
+                }
+            }
+        }
+        Ok(runs)
+    }
+}
+
+fn format_run(run: &Finished) -> String {
+    let outcome = match run.outcome {
+        Outcome::Exited(code) => format!("exit={}", code),
+        Outcome::Signaled(signal) => format!("signal={}", signal),
+        Outcome::TimedOut => "timeout".to_owned(),
+    };
+    format!(
+        "{} {} {} {} {} {} {} {} {}\n",
+        run.scheduled,
+        run.started,
+        run.ended,
+        run.pid,
+        run.attempt,
+        outcome,
+        run.output,
+        run.line,
+        run.source
+    )
+}
+
+fn parse_run(line: &str) -> Option<Finished> {
+    let mut parts = line.splitn(9, ' ');
+    let mut next = || parts.next();
+    let scheduled = next()?.parse().ok()?;
+    let started = next()?.parse().ok()?;
+    let ended = next()?.parse().ok()?;
+    let pid = next()?.parse().ok()?;
+    let attempt = next()?.parse().ok()?;
+    let outcome = match next()? {
+        "timeout" => Outcome::TimedOut,
+        outcome if outcome.starts_with("exit=") => Outcome::Exited(outcome[5..].parse().ok()?),
+        outcome if outcome.starts_with("signal=") => Outcome::Signaled(outcome[7..].parse().ok()?),
+        _ => return None,
+    };
+    let output = next()?.parse().ok()?;
+    let line = next()?.parse().ok()?;
+    let source = next()?.to_owned();
+    Some(Finished {
+        line,
+        source,
+        pid,
+        scheduled,
+        attempt,
+        started,
+        ended,
+        outcome,
+        output,
+    })
+}
+
+/// Which runs `crondy history` shows.
+#[derive(Debug, Default, PartialEq)]
+pub struct Filter {
+    /// A line number, or text to look for in the entry.
+    pub entry: Option<String>,
+    /// Runs that started at or after this second.
+    pub since: Option<i64>,
+    /// Runs that started before this second.
+    pub until: Option<i64>,
+    pub outcome: Option<OutcomeFilter>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OutcomeFilter {
+    Success,
+    Failure,
+    Exited(i32),
+    Signaled,
+    TimedOut,
+}
+
+impl OutcomeFilter {
+    fn parse(text: &str) -> Result<Self, Error> {
+        Ok(match text {
+            "success" | "ok" => OutcomeFilter::Success,
+            "failure" | "failed" => OutcomeFilter::Failure,
+            "signal" | "signaled" => OutcomeFilter::Signaled,
+            "timeout" => OutcomeFilter::TimedOut,
+            _ => match text.parse() {
+                Ok(code) => OutcomeFilter::Exited(code),
+                Err(_) => bail!(
+                    "expected success, failure, signal, timeout or an exit status, got {:?}",
+                    text
+                ),
+            },
+        })
+    }
+
+    fn matches(self, outcome: Outcome) -> bool {
+        match (self, outcome) {
+            (OutcomeFilter::Success, outcome) => outcome == Outcome::Exited(0),
+            (OutcomeFilter::Failure, outcome) => outcome != Outcome::Exited(0),
+            (OutcomeFilter::Exited(code), Outcome::Exited(exited)) => code == exited,
+            (OutcomeFilter::Signaled, Outcome::Signaled(_)) => true,
+            (OutcomeFilter::TimedOut, Outcome::TimedOut) => true,
+            _ => false,
+        }
+    }
+}
+
+impl Filter {
+    /// Read `--entry`, `--since`, `--until` and `--outcome` options.
+    pub fn parse(args: &[String]) -> Result<Self, Error> {
+        let mut filter = Filter::default();
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            let (name, value) = match arg.find('=') {
+                Some(at) => (&arg[..at], Some(arg[at + 1..].to_owned())),
+                None => (arg.as_str(), None),
+            };
+            let value = match value.or_else(|| args.next().cloned()) {
+                Some(value) => value,
+                None => bail!("{} needs a value", name),
+            };
+            let time = |value: &str| {
+                clock::parse_seconds(value).ok_or_else(|| {
+                    format_err!(
+                        "{}: expected YYYY-MM-DD[THH:MM[:SS]], got {:?}",
+                        name,
+                        value
+                    )
+                })
+            };
+            match name {
+                "--entry" => filter.entry = Some(value),
+                "--since" => filter.since = Some(time(&value)?),
+                "--until" => filter.until = Some(time(&value)?),
+                "--outcome" => filter.outcome = Some(OutcomeFilter::parse(&value)?),
+                _ => bail!("unknown option {:?}", name),
+            }
+        }
+        Ok(filter)
+    }
+
+    pub fn matches(&self, run: &Finished) -> bool {
+        let entry = match &self.entry {
+            Some(entry) => match entry.parse::<usize>() {
+                Ok(line) => run.line == line,
+                Err(_) => run.source.contains(entry.as_str()),
+            },
+            None => true,
+        };
+        entry
+            && self.since.is_none_or(|since| run.started >= since)
+            && self.until.is_none_or(|until| run.started < until)
+            && self.outcome.is_none_or(|outcome| outcome.matches(run.outcome))
+    }
+}
+
+/// One run the way `crondy history` shows it.
+pub fn describe(run: &Finished) -> String {
+    format!(
+        "{}  {}  {:>6}s  {:<24}  {:>8}B  line {} (attempt {}): {}",
+        Moment::at(run.scheduled),
+        clock::format_seconds(run.started),
+        run.ended - run.started,
+        run.outcome.to_string(),
+        run.output,
+        run.line,
+        run.attempt,
+        run.source
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_files::temporary;
+
+    fn history(name: &str) -> History {
+        let history = History::new(&temporary(&format!("history-{}", name)));
+        for n in 0..=history.keep {
+            let _ = fs::remove_file(history.rotated(n));
+        }
+        history
+    }
+
+    fn clean_up(history: &History) {
+        for n in 1..=history.keep {
+            let _ = fs::remove_file(history.rotated(n));
+        }
+        let _ = fs::remove_file(&history.path);
+    }
+
+    fn run(started: i64, outcome: Outcome) -> Finished {
+        Finished {
+            line: 3,
+            source: "0 * * * * backup  --all".to_owned(),
+            pid: 1234,
+            scheduled: started / 60,
+            attempt: 1,
+            started,
+            ended: started + 5,
+            outcome,
+            output: 42,
+        }
+    }
+
+    #[test]
+    fn round_trips_runs() {
+        for outcome in &[Outcome::Exited(0), Outcome::Signaled(9), Outcome::TimedOut] {
+            let run = run(600, *outcome);
+            assert_eq!(parse_run(format_run(&run).trim_end()), Some(run));
+        }
+    }
+
+    #[test]
+    fn appends_and_reads_back() {
+        let history = history("append");
+        history.append(&run(60, Outcome::Exited(0))).unwrap();
+        history.append(&run(120, Outcome::Exited(1))).unwrap();
+        let runs = history.read().unwrap();
+        clean_up(&history);
+        assert_eq!(
+            runs,
+            vec![run(60, Outcome::Exited(0)), run(120, Outcome::Exited(1))]
+        );
+    }
+
+    #[test]
+    fn rotates_and_drops_the_oldest() {
+        let mut history = history("rotate");
+        history.max_size = 1;
+        history.keep = 2;
+        for started in 0..5 {
+            history.append(&run(started, Outcome::Exited(0))).unwrap();
+        }
+        let started: Vec<i64> = history.read().unwrap().iter().map(|r| r.started).collect();
+        assert!(history.rotated(2).exists());
+        assert!(!history.rotated(3).exists());
+        clean_up(&history);
+        assert_eq!(started, vec![2, 3, 4]);
+    }
+
+    #[test]
+    fn skips_garbage() {
+        let history = history("garbage");
+        let line = format_run(&run(60, Outcome::Exited(0)));
+        fs::write(&history.path, format!("yesterday it ran\n{}{}", line, &line[..10])).unwrap();
+        let result = history.read();
+        clean_up(&history);
+        assert_eq!(result.unwrap(), vec![run(60, Outcome::Exited(0))]);
+    }
+
+    fn filter(args: &[&str]) -> Filter {
+        let args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+        Filter::parse(&args).unwrap()
+    }
+
+    #[test]
+    fn filters_by_entry() {
+        let run = run(0, Outcome::Exited(0));
+        assert!(filter(&["--entry", "backup"]).matches(&run));
+        assert!(filter(&["--entry=3"]).matches(&run));
+        assert!(!filter(&["--entry", "4"]).matches(&run));
+        assert!(!filter(&["--entry", "rotate"]).matches(&run));
+    }
+
+    #[test]
+    fn filters_by_time() {
+        let filter = filter(&["--since", "1970-01-01T01:00", "--until", "1970-01-02"]);
+        assert!(!filter.matches(&run(3599, Outcome::Exited(0))));
+        assert!(filter.matches(&run(3600, Outcome::Exited(0))));
+        assert!(!filter.matches(&run(86400, Outcome::Exited(0))));
+    }
+
+    #[test]
+    fn filters_by_outcome() {
+        let failure = filter(&["--outcome", "failure"]);
+        assert!(!failure.matches(&run(0, Outcome::Exited(0))));
+        assert!(failure.matches(&run(0, Outcome::TimedOut)));
+        assert!(filter(&["--outcome", "2"]).matches(&run(0, Outcome::Exited(2))));
+        assert!(!filter(&["--outcome", "signal"]).matches(&run(0, Outcome::Exited(2))));
+    }
+
+    #[test]
+    fn rejects_bad_filters() {
+        let parse = |args: &[&str]| {
+            Filter::parse(&args.iter().map(|arg| arg.to_string()).collect::<Vec<_>>())
+        };
+        assert!(parse(&["--since", "yesterday"]).is_err());
+        assert!(parse(&["--outcome", "meh"]).is_err());
+        assert!(parse(&["--entry"]).is_err());
+        assert!(parse(&["--verbose"]).is_err());
+    }
+}
@@ -0,0 +1,243 @@
+use std::fmt;
+use std::io::{self, Read, Write};
+use std::os::unix::process::{CommandExt, ExitStatusExt};
+use std::process::{Child, Command, ExitStatus, Stdio};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use crondy::crontab::Entry;
+use crondy::user::User;
+use log::{debug, warn};
+
+/// How a job ended.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Outcome {
+    Exited(i32),
+    Signaled(i32),
+    /// Ran past its timeout and had to be stopped.
+    TimedOut,
+}
+
+impl Outcome {
+    pub fn from_status(status: ExitStatus) -> Self {
+        match (status.code(), status.signal()) {
+            (Some(code), _) => Outcome::Exited(code),
+            (None, Some(signal)) => Outcome::Signaled(signal),
+            (None, None) => unreachable!("exit status without code or signal"),
+        }
+    }
+}
+
+impl fmt::Display for Outcome {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Outcome::Exited(code) => write!(f, "exited with status {}", code),
+            Outcome::Signaled(signal) => write!(f, "killed by signal {}", signal),
+            Outcome::TimedOut => write!(f, "timed out"),
+        }
+    }
+}
+
+/// A started job, and the bytes of output it has written so far.
+#[derive(Debug)]
+pub struct Job {
+    pub child: Child,
+    output: Arc<AtomicU64>,
+    copiers: Vec<JoinHandle<()>>,
+}
+
+impl Job {
+    /*
+     * How much the job wrote to stdout and stderr. Once the job has exited
+     * the rest of its output may still be in the pipes, so give the copying
+     * threads a moment to finish; they won't if something the job started
+     * still holds the pipes open, and then this is only a lower bound.
+     */
+    pub fn output_size(&mut self) -> u64 {
+        let deadline = Instant::now() + Duration::from_millis(100);
+        while self.copiers.iter().any(|copier| !copier.is_finished()) && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(1));
+        }
+        for copier in self.copiers.drain(..).filter(JoinHandle::is_finished) {
+            let _ = copier.join();
+        }
+        self.output.load(Ordering::Relaxed)
+    }
+}
+
+/*
+ * Pass everything read from `from` on to `to`, counting it in `output`.
+ */
+fn copy_output(
+    mut from: impl Read + Send + 'static,
+    mut to: impl Write + Send + 'static,
+    output: Arc<AtomicU64>,
+) -> JoinHandle<()> {
+    thread::spawn(move || {
+        let mut buf = [0; 8192];
+        loop {
+            match from.read(&mut buf) {
+                Ok(0) => break,
+                Ok(n) => {
+                    output.fetch_add(n as u64, Ordering::Relaxed);
+                    let _ = to.write_all(&buf[..n]);
+                }
+                Err(ref error) if error.kind() == io::ErrorKind::Interrupted => continue,
+                Err(_) => break,
+            }
+        }
+    })
+}
+
+/// Send `signal` to the process group of the job with the given pid.
+pub fn signal(pid: u32, signal: libc::c_int) {
+    unsafe {
+        libc::kill(-(pid as libc::pid_t), signal);
+    }
+}
+
+/// Start `entry` under `$SHELL -c` in `$HOME`, with the environment from
+/// `Entry::environment`, feeding it whatever followed the first `%` on stdin.
+/// The job leads a process group of its own, so that it can be stopped along
+/// with everything it started. Which attempt at the run this is, counting
+/// from 1, is passed on in `CRONDY_ATTEMPT`. The job's output goes to
+/// crondy's own stdout and stderr, and is counted on the way.
+pub fn spawn(entry: &Entry, owner: &User, attempt: u32) -> io::Result<Job> {
+    let mut env = entry.environment(owner);
+    env.push(("CRONDY_ATTEMPT".to_owned(), attempt.to_string()));
+    debug!("environment for {:?}: {:?}", entry.command(), env);
+    let lookup = |name| {
+        env.iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, value)| value.as_str())
+            .unwrap_or_default()
+    };
+
+    let mut command = Command::new(lookup("SHELL"));
+    command
+        .arg("-c")
+        .arg(entry.command())
+        .current_dir(lookup("HOME"))
+        .env_clear()
+        .envs(env.iter().map(|(name, value)| (name, value)))
+        .process_group(0);
+    command.stdin(if entry.stdin().is_some() {
+        Stdio::piped()
+    } else {
+        Stdio::null()
+    });
+    command.stdout(Stdio::piped()).stderr(Stdio::piped());
+
+    let mut child = command.spawn()?;
+    debug!("started job {} for {:?}", child.id(), entry.command());
+
+    /*
+     * The job may not read its input before writing a lot of output, or at
+     * all, so write it from another thread to keep the scheduler going. A
+     * job that exits without reading everything is not our problem.
+     */
+    if let (Some(input), Some(mut pipe)) = (entry.stdin(), child.stdin.take()) {
+        let input = input.to_owned();
+        let pid = child.id();
+        thread::spawn(move || {
+            if let Err(error) = pipe.write_all(input.as_bytes()) {
+                warn!("could not write input of job {}: {}", pid, error);
+            }
+        });
+    }
+
+    let output = Arc::new(AtomicU64::new(0));
+    let mut copiers = Vec::new();
+    if let Some(stdout) = child.stdout.take() {
+        copiers.push(copy_output(stdout, io::stdout(), output.clone()));
+    }
+    if let Some(stderr) = child.stderr.take() {
+        copiers.push(copy_output(stderr, io::stderr(), output.clone()));
+    }
+    Ok(Job {
+        child,
+        output,
+        copiers,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_files::temporary;
+    use crondy::crontab::Crontab;
+    use std::fs;
+
+    fn owner() -> User {
+        User::current().unwrap()
+    }
+
+    fn only_entry(crontab: &Crontab) -> &Entry {
+        crontab.reboot_jobs()[0]
+    }
+
+    #[test]
+    fn feeds_stdin() {
+        let path = temporary("stdin");
+        let tab = Crontab::parse(&format!(
+            "@reboot cat > {}%first%second \\% line",
+            path.display()
+        ))
+        .unwrap();
+        let status = spawn(only_entry(&tab), &owner(), 1)
+            .unwrap()
+            .child
+            .wait()
+            .unwrap();
+        assert!(status.success());
+        let written = fs::read_to_string(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(written, "first\nsecond % line\n");
+    }
+
+    #[test]
+    fn runs_with_default_env() {
+        let tab = Crontab::parse(
+            "@reboot test \"$PATH\" = /usr/bin:/bin -a \"$SHELL\" = /bin/sh -a \"$PWD\" = \"$HOME\"",
+        )
+        .unwrap();
+        let status = spawn(only_entry(&tab), &owner(), 1)
+            .unwrap()
+            .child
+            .wait()
+            .unwrap();
+        assert!(status.success());
+    }
+
+    #[test]
+    fn passes_attempt() {
+        let tab = Crontab::parse("@reboot test \"$CRONDY_ATTEMPT\" = 3").unwrap();
+        let status = spawn(only_entry(&tab), &owner(), 3)
+            .unwrap()
+            .child
+            .wait()
+            .unwrap();
+        assert!(status.success());
+    }
+
+    #[test]
+    fn counts_output() {
+        let tab = Crontab::parse("@reboot echo hello; echo oops >&2").unwrap();
+        let mut job = spawn(only_entry(&tab), &owner(), 1).unwrap();
+        assert!(job.child.wait().unwrap().success());
+        assert_eq!(job.output_size(), 11);
+    }
+
+    #[test]
+    fn passes_crontab_env() {
+        let tab = Crontab::parse("FOO = bar baz\n@reboot test \"$FOO\" = 'bar baz'").unwrap();
+        let status = spawn(only_entry(&tab), &owner(), 1)
+            .unwrap()
+            .child
+            .wait()
+            .unwrap();
+        assert!(status.success());
+    }
+}
@@ -0,0 +1,34 @@
+use std::fmt::Write;
+
+/// `text` as a JSON string, quotes included.
+pub fn string(text: &str) -> String {
+    let mut quoted = String::with_capacity(text.len() + 2);
+    quoted.push('"');
+    for c in text.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            c if (c as u32) < 0x20 => {
+                let _ = write!(quoted, "\\u{:04x}", c as u32);
+            }
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn quotes_strings() {
+        assert_eq!(string("backup --all"), "\"backup --all\"");
+        assert_eq!(string("say \"hi\"\\n"), "\"say \\\"hi\\\"\\\\n\"");
+        assert_eq!(string("a\nb\tc\u{1}"), "\"a\\nb\\tc\\u0001\"");
+    }
+}
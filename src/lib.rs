@@ -0,0 +1,39 @@
+//! crondy's crontab parser and schedule matcher, for programs that want to
+//! read crontabs or work out when things are due without running the daemon.
+//!
+//! ```
+//! use crondy::clock::{self, Moment};
+//! use crondy::{Crontab, Schedule};
+//!
+//! # fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! let schedule: Schedule = "0 9 * * 1-5".parse()?;
+//! let saturday = clock::parse_seconds("2018-10-20T12:00").unwrap() / 60;
+//! let next = schedule.next_after(saturday).unwrap();
+//! assert_eq!(Moment::at(next).to_string(), "2018-10-22T09:00");
+//!
+//! let crontab = Crontab::parse("MAILTO=ops\n*/5 * * * * backup --quick\n")?;
+//! crontab.validate()?;
+//! let due = crontab.find_jobs(&Moment::at(next), true, true);
+//! assert_eq!(due[0].command(), "backup --quick");
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Time is counted in minutes on the local wall clock, see `clock`.
+
+#[cfg(test)]
+#[macro_use]
+mod test_helpers;
+pub mod clock;
+pub mod crontab;
+mod describe;
+mod normalize;
+pub mod options;
+pub mod paths;
+pub mod schedule;
+pub mod user;
+
+pub use crate::clock::{Minute, Moment};
+pub use crate::crontab::{Crontab, CrontabError, Entry, Problem, Severity, Syntax};
+pub use crate::options::Options;
+pub use crate::schedule::{Field, Schedule, ScheduleError, When};
@@ -0,0 +1,445 @@
+use std::cell::RefCell;
+use std::fmt::Write as _;
+use std::io::{self, Write};
+use std::os::unix::net::UnixDatagram;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+
+use crondy::clock::{self, Minute, Moment};
+use crondy::crontab::Entry;
+use failure::{bail, format_err, Error};
+use log::{Level, Log, Metadata, Record};
+
+use crate::job::Outcome;
+use crate::json;
+
+/// Where the syslog backend sends messages when not told otherwise.
+pub const DEFAULT_SYSLOG_SOCKET: &str = "/dev/log";
+
+/// How log messages are written.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Backend {
+    /// For people: colourful lines on stderr.
+    Pretty,
+    /// For machines: a JSON object per line on stderr.
+    Json,
+    /// To the syslog daemon listening on a socket, as in `syslog:/dev/log`.
+    Syslog(PathBuf),
+}
+
+impl Backend {
+    pub fn parse(name: &str) -> Result<Self, Error> {
+        match name {
+            "pretty" => Ok(Backend::Pretty),
+            "json" => Ok(Backend::Json),
+            "syslog" => Ok(Backend::Syslog(PathBuf::from(DEFAULT_SYSLOG_SOCKET))),
+            _ if name.starts_with("syslog:") && name.len() > "syslog:".len() => {
+                Ok(Backend::Syslog(PathBuf::from(&name["syslog:".len()..])))
+            }
+            _ => bail!(
+                "expected pretty, json, syslog or syslog:SOCKET as the log format, got {:?}",
+                name
+            ),
+        }
+    }
+}
+
+/*
+ * Send log messages to `backend`, keeping the ones `filters` lets through:
+ * a level such as `info`, or levels for modules as in
+ * `info,crondy::executor=debug`. Without filters, only errors are shown to
+ * people, while JSON and syslog get to know what was started as well.
+ */
+pub fn init(backend: &Backend, filters: Option<&str>) -> Result<(), Error> {
+    let filters = filters.unwrap_or_else(|| default_filters(backend));
+    match backend {
+        Backend::Pretty => {
+            let mut builder = pretty_env_logger::formatted_builder()?;
+            builder.parse(filters);
+            builder.try_init()?;
+        }
+        Backend::Json => {
+            let filter = filter(filters);
+            log::set_max_level(filter.filter());
+            STRUCTURED.store(true, Ordering::Relaxed);
+            let _ = log::set_boxed_logger(Box::new(JsonLogger { filter }));
+        }
+        Backend::Syslog(socket) => {
+            let logger = SyslogLogger::connect(socket, filter(filters))?;
+            log::set_max_level(logger.filter.filter());
+            let _ = log::set_boxed_logger(Box::new(logger));
+        }
+    }
+    Ok(())
+}
+
+fn default_filters(backend: &Backend) -> &'static str {
+    match backend {
+        Backend::Pretty => "error",
+        Backend::Json | Backend::Syslog(_) => "info",
+    }
+}
+
+fn filter(filters: &str) -> env_logger::filter::Filter {
+    env_logger::filter::Builder::new().parse(filters).build()
+}
+
+/*
+ * Something that happened to crondy or one of its jobs, for the backends
+ * that keep track of more than the message. Logged with `event!`:
+ *
+ *     event!(Level::Info, Event { kind: "job_started", .. }, "({}) CMD ({})", user, cmd)
+ *
+ * The kinds are
+ *
+ *     crondy_started, crondy_stopping, crondy_stopped,
+ *     crontab_reloaded, crontab_reload_failed,
+ *     scheduler_paused, scheduler_resumed, entry_paused, entry_resumed,
+ *     catching_up, run_requested,
+ *     job_started, job_not_started, job_finished, job_skipped, job_queued,
+ *     job_killed, job_timed_out, retry_scheduled, retry_dropped
+ *
+ * and the JSON backend writes them with these fields, leaving out the ones
+ * that don't apply:
+ *
+ *     event       the kind, or "message" for anything logged without one
+ *     entry       the entry as written in the crontab
+ *     file, line  where the entry is
+ *     scheduled   the minute the run was due, as YYYY-MM-DDTHH:MM
+ *     pid         the job's process id
+ *     attempt     1 for the first run, 2 for the first retry and so on
+ *     outcome     "exited", "signaled" or "timed_out"
+ *     exit_status, signal
+ *     duration    how long the job ran, in seconds
+ */
+#[derive(Debug, Default)]
+pub struct Event<'a> {
+    pub kind: &'static str,
+    pub entry: Option<&'a Entry>,
+    pub scheduled: Option<Minute>,
+    pub pid: Option<u32>,
+    pub attempt: Option<u32>,
+    pub outcome: Option<Outcome>,
+    pub duration: Option<i64>,
+}
+
+impl<'a> Event<'a> {
+    /// The fields as JSON object members, without the braces.
+    fn fields(&self) -> String {
+        let mut out = format!("\"event\":{}", json::string(self.kind));
+        if let Some(entry) = self.entry {
+            let _ = write!(out, ",\"entry\":{}", json::string(entry.source()));
+            if let Some(file) = entry.file() {
+                let _ = write!(out, ",\"file\":{}", json::string(file));
+            }
+            let _ = write!(out, ",\"line\":{}", entry.line());
+        }
+        if let Some(scheduled) = self.scheduled {
+            let _ = write!(out, ",\"scheduled\":\"{}\"", Moment::at(scheduled));
+        }
+        if let Some(pid) = self.pid {
+            let _ = write!(out, ",\"pid\":{}", pid);
+        }
+        if let Some(attempt) = self.attempt {
+            let _ = write!(out, ",\"attempt\":{}", attempt);
+        }
+        match self.outcome {
+            Some(Outcome::Exited(status)) => {
+                let _ = write!(out, ",\"outcome\":\"exited\",\"exit_status\":{}", status);
+            }
+            Some(Outcome::Signaled(signal)) => {
+                let _ = write!(out, ",\"outcome\":\"signaled\",\"signal\":{}", signal);
+            }
+            Some(Outcome::TimedOut) => out.push_str(",\"outcome\":\"timed_out\""),
+            None => (),
+        }
+        if let Some(duration) = self.duration {
+            let _ = write!(out, ",\"duration\":{}", duration);
+        }
+        out
+    }
+}
+
+/// Log a message with `log!`, and the `Event` it is about along with it.
+macro_rules! event {
+    ($level:expr, $event:expr, $($arg:tt)+) => {
+        $crate::logging::with_event(&$event, || log::log!($level, $($arg)+))
+    };
+}
+
+/* Whether a backend that wants events is in use. */
+static STRUCTURED: AtomicBool = AtomicBool::new(false);
+
+thread_local! {
+    /* The fields of the event being logged, if any. */
+    static EVENT: RefCell<Option<String>> = const { RefCell::new(None) };
+}
+
+pub fn with_event(event: &Event, log: impl FnOnce()) {
+    if !STRUCTURED.load(Ordering::Relaxed) {
+        return log();
+    }
+    EVENT.with(|current| *current.borrow_mut() = Some(event.fields()));
+    log();
+    EVENT.with(|current| *current.borrow_mut() = None);
+}
+
+struct JsonLogger {
+    filter: env_logger::filter::Filter,
+}
+
+impl Log for JsonLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        self.filter.enabled(metadata)
+    }
+
+    fn log(&self, record: &Record) {
+        if let Some(line) = self.line(record) {
+            let _ = io::stderr().write_all(line.as_bytes());
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+impl JsonLogger {
+    /// The line to write for `record`, if the filter lets it through.
+    fn line(&self, record: &Record) -> Option<String> {
+        if !self.filter.matches(record) {
+            return None;
+        }
+        Some(EVENT.with(|event| {
+            json_line(
+                &clock::format_seconds(clock::now_seconds()),
+                record,
+                event.borrow().as_deref(),
+            )
+        }))
+    }
+}
+
+fn json_line(time: &str, record: &Record, fields: Option<&str>) -> String {
+    format!(
+        "{{\"time\":\"{}\",\"level\":\"{}\",{},\"message\":{},\"target\":{}}}\n",
+        time,
+        record.level().to_string().to_lowercase(),
+        fields.unwrap_or("\"event\":\"message\""),
+        json::string(&record.args().to_string()),
+        json::string(record.target())
+    )
+}
+
+/* Facility 9 is cron. */
+const FACILITY: u8 = 9;
+
+/*
+ * Writes RFC 3164 messages to a syslog daemon's datagram socket, the way
+ * syslog(3) does:
+ *
+ *     <78>Oct 15 03:00:00 crondy[123]: (root) CMD (run-parts /etc/cron.daily)
+ */
+struct SyslogLogger {
+    filter: env_logger::filter::Filter,
+    path: PathBuf,
+    socket: Mutex<UnixDatagram>,
+}
+
+impl SyslogLogger {
+    fn connect(path: &Path, filter: env_logger::filter::Filter) -> Result<Self, Error> {
+        let socket = Self::open(path)
+            .map_err(|error| format_err!("could not connect to {}: {}", path.display(), error))?;
+        Ok(SyslogLogger {
+            filter,
+            path: path.to_owned(),
+            socket: Mutex::new(socket),
+        })
+    }
+
+    fn open(path: &Path) -> io::Result<UnixDatagram> {
+        let socket = UnixDatagram::unbound()?;
+        socket.connect(path)?;
+        Ok(socket)
+    }
+}
+
+impl Log for SyslogLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        self.filter.enabled(metadata)
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.filter.matches(record) {
+            return;
+        }
+        let message = syslog_message(clock::now_seconds(), record);
+        let mut socket = self.socket.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        if socket.send(message.as_bytes()).is_err() {
+            // the syslog daemon may have been restarted since
+            if let Ok(reopened) = Self::open(&self.path) {
+                *socket = reopened;
+                let _ = socket.send(message.as_bytes());
+            }
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+fn syslog_message(now: i64, record: &Record) -> String {
+    const MONTHS: [&str; 12] = [
+        "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+    ];
+    let severity = match record.level() {
+        Level::Error => 3,
+        Level::Warn => 4,
+        Level::Info => 6,
+        Level::Debug | Level::Trace => 7,
+    };
+    let moment = Moment::at(now.div_euclid(60));
+    format!(
+        "<{}>{} {:2} {:02}:{:02}:{:02} crondy[{}]: {}",
+        FACILITY * 8 + severity,
+        MONTHS[moment.month - 1],
+        moment.day_of_month,
+        moment.hour,
+        moment.minute,
+        now.rem_euclid(60),
+        process::id(),
+        record.args()
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_files::temporary;
+    use crondy::crontab::Crontab;
+
+    #[test]
+    fn parses_backends() {
+        assert_eq!(Backend::parse("json").unwrap(), Backend::Json);
+        assert_eq!(
+            Backend::parse("syslog").unwrap(),
+            Backend::Syslog(PathBuf::from("/dev/log"))
+        );
+        assert_eq!(
+            Backend::parse("syslog:/tmp/log").unwrap(),
+            Backend::Syslog(PathBuf::from("/tmp/log"))
+        );
+        assert!(Backend::parse("syslog:").is_err());
+        assert!(Backend::parse("xml").is_err());
+    }
+
+    #[test]
+    fn describes_events() {
+        let tab = Crontab::parse("@daily backup").unwrap().in_file("/etc/crontab");
+        let event = Event {
+            kind: "job_finished",
+            entry: Some(&tab.entries()[0]),
+            scheduled: Some(60),
+            pid: Some(42),
+            attempt: Some(1),
+            outcome: Some(Outcome::Exited(3)),
+            duration: Some(10),
+        };
+        assert_eq!(
+            event.fields(),
+            "\"event\":\"job_finished\",\"entry\":\"@daily backup\",\"file\":\"/etc/crontab\",\
+             \"line\":1,\"scheduled\":\"1970-01-01T01:00\",\"pid\":42,\"attempt\":1,\
+             \"outcome\":\"exited\",\"exit_status\":3,\"duration\":10"
+        );
+        let event = Event {
+            kind: "job_timed_out",
+            outcome: Some(Outcome::TimedOut),
+            ..Event::default()
+        };
+        assert_eq!(event.fields(), "\"event\":\"job_timed_out\",\"outcome\":\"timed_out\"");
+    }
+
+    #[test]
+    fn writes_a_line_per_record() {
+        let line = |fields| {
+            json_line(
+                "1970-01-01T00:00:00",
+                &Record::builder()
+                    .args(format_args!("CMD (say \"hi\")"))
+                    .level(Level::Info)
+                    .target("crondy::executor")
+                    .build(),
+                fields,
+            )
+        };
+        assert_eq!(
+            line(None),
+            "{\"time\":\"1970-01-01T00:00:00\",\"level\":\"info\",\"event\":\"message\",\
+             \"message\":\"CMD (say \\\"hi\\\")\",\"target\":\"crondy::executor\"}\n"
+        );
+        assert!(line(Some("\"event\":\"job_started\",\"pid\":1"))
+            .contains(",\"event\":\"job_started\",\"pid\":1,\"message\":"));
+    }
+
+    #[test]
+    fn logs_what_jobs_did_as_json_by_default() {
+        let logger = JsonLogger {
+            filter: filter(default_filters(&Backend::Json)),
+        };
+        let record = |level| {
+            logger.line(
+                &Record::builder()
+                    .args(format_args!("(root) CMD (true) exited with 0"))
+                    .level(level)
+                    .target("crondy::executor")
+                    .build(),
+            )
+        };
+        let event = Event {
+            kind: "job_finished",
+            ..Event::default()
+        };
+        EVENT.with(|current| *current.borrow_mut() = Some(event.fields()));
+        let line = record(Level::Info);
+        let debug = record(Level::Debug);
+        EVENT.with(|current| *current.borrow_mut() = None);
+        assert!(line.unwrap().contains(",\"event\":\"job_finished\","));
+        assert_eq!(debug, None);
+    }
+
+    #[test]
+    fn sends_to_syslog() {
+        let path = temporary("syslog");
+        let daemon = UnixDatagram::bind(&path).unwrap();
+        let logger = SyslogLogger::connect(&path, filter("info")).unwrap();
+        let record = |level| {
+            logger.log(
+                &Record::builder()
+                    .args(format_args!("(root) CMD (true)"))
+                    .level(level)
+                    .build(),
+            )
+        };
+        record(Level::Debug);
+        record(Level::Info);
+        let mut buf = [0; 512];
+        let size = daemon.recv(&mut buf).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let message = String::from_utf8_lossy(&buf[..size]);
+        assert!(message.starts_with("<78>"), "{}", message);
+        assert!(message.ends_with(&format!(" crondy[{}]: (root) CMD (true)", process::id())));
+    }
+
+    #[test]
+    fn formats_syslog_messages() {
+        let record = Record::builder()
+            .args(format_args!("oops"))
+            .level(Level::Error)
+            .build();
+        // 1970-01-02T03:04:05
+        let now = ((24 + 3) * 60 + 4) * 60 + 5;
+        assert_eq!(
+            syslog_message(now, &record),
+            format!("<75>Jan  2 03:04:05 crondy[{}]: oops", process::id())
+        );
+    }
+}
@@ -1,188 +1,789 @@
-use std::sync::atomic::AtomicBool;
-use std::sync::Arc;
-use std::process;
-use std::fs::File;
+use std::fs::{self, File};
 use std::io::Read;
-use std::time::SystemTime;
+use std::path::Path;
+use std::process;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
 
-use log::{debug, error, info};
+use crondy::clock::{self, Minute, Moment};
+use crondy::user::User;
+use crondy::{Crontab, Entry, Problem, Schedule, Severity, Syntax};
+use failure::{bail, format_err, Error};
+use log::{debug, error, info, warn, Level};
 
-#[cfg(test)]
 #[macro_use]
-mod test_helpers;
-mod crontab;
-mod schedule;
+mod logging;
+mod cli;
+mod control;
+mod daemon;
+mod executor;
+mod history;
+mod job;
+mod json;
+mod metrics;
+mod next;
+mod pidfile;
+mod scheduler;
+mod simulate;
+mod state;
+#[cfg(test)]
+mod test_files;
 
-use crate::crontab::Crontab;
+use crate::cli::{Action, Config};
+use crate::control::{Command, Paused, Server, Target};
+use crate::executor::Executor;
+use crate::history::{Filter, History};
+use crate::logging::Event;
+use crate::next::Query;
+use crate::pidfile::Pidfile;
+use crate::scheduler::Scheduler;
+use crate::simulate::Simulation;
+use crate::state::LastRuns;
 
 fn main() {
-    pretty_env_logger::init_custom_env("CRONDY_LOG");
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let (config, action) = cli::parse(&args, &|name| std::env::var(name).ok())
+        .unwrap_or_else(|error| {
+            eprintln!("crondy: {}\nsee crondy --help", error);
+            process::exit(2);
+        });
+    if let Err(error) = logging::init(&config.log_format, config.log_filters.as_deref()) {
+        eprintln!("crondy: {}", error);
+        process::exit(1);
+    }
+
+    let (name, result) = match &action {
+        Action::Run => ("", Ok(None)),
+        Action::Help => ("", Ok(Some((cli::USAGE.to_owned(), 0)))),
+        Action::History(args) => ("history", show_history(&config, args).map(|out| Some((out, 0)))),
+        Action::Next(args) => ("next", show_next(&config, args).map(|out| Some((out, 0)))),
+        Action::Check(args) => ("check", check(args).map(Some)),
+        Action::Simulate(args) => ("simulate", simulate(&config, args).map(|out| Some((out, 0)))),
+    };
+    match result {
+        Ok(Some((output, status))) => {
+            print!("{}", output);
+            process::exit(status);
+        }
+        Err(error) => {
+            eprintln!("crondy {}: {}", name, error);
+            process::exit(1);
+        }
+        Ok(None) => run(config),
+    }
+}
+
+fn run(mut config: Config) {
+    let daemon = if config.daemon {
+        match std::env::current_dir() {
+            Ok(cwd) => config.make_absolute(&cwd),
+            Err(error) => {
+                error!("could not find the current directory: {}", error);
+                process::exit(1);
+            }
+        }
+        match daemon::detach() {
+            Ok(daemon) => Some(daemon),
+            Err(error) => {
+                error!("{}", error);
+                process::exit(1);
+            }
+        }
+    } else {
+        None
+    };
 
     let child_died = Arc::new(AtomicBool::new(false));
-    match signal_hook::flag::register(signal_hook::SIGCHLD, Arc::clone(&child_died)) {
-        Ok(_) => debug!("registered signal hook"),
+    let reload = Arc::new(AtomicBool::new(false));
+    let stop = Arc::new(AtomicUsize::new(0)); // the signal to stop, once there is one
+    let registered = signal_hook::flag::register(signal_hook::SIGCHLD, Arc::clone(&child_died))
+        .and_then(|_| signal_hook::flag::register(signal_hook::SIGHUP, Arc::clone(&reload)))
+        .and_then(|_| {
+            signal_hook::flag::register_usize(
+                signal_hook::SIGTERM,
+                Arc::clone(&stop),
+                signal_hook::SIGTERM as usize,
+            )
+        })
+        .and_then(|_| {
+            signal_hook::flag::register_usize(
+                signal_hook::SIGINT,
+                Arc::clone(&stop),
+                signal_hook::SIGINT as usize,
+            )
+        });
+    match registered {
+        Ok(_) => debug!("registered signal hooks"),
         Err(error) => {
             error!("failed to register signal handlers: {}", error);
             process::exit(1);
         }
     }
 
-    info!("starting up!");
-    let crontab_path = std::env::var("CRONTAB").unwrap_or("/etc/crontab".to_owned());
-    debug!("selected crontab: {}", &crontab_path);
+    event!(
+        Level::Info,
+        Event {
+            kind: "crondy_started",
+            ..Event::default()
+        },
+        "starting up!"
+    );
+    debug!("selected crontabs: {:?}", config.crontabs);
 
     debug!("loading database");
-    let mut crontab_file = File::open(&crontab_path).unwrap_or_else(|error| {
-        use std::io::ErrorKind;
-        match error.kind() {
-            ErrorKind::NotFound => error!("could not find crontab file at path {}", &crontab_path),
-            _ => error!("error opening crontab: {}", error),
-        }
+    let mut crontab = load_crontabs(&config.crontabs).unwrap_or_else(|error| {
+        error!("{}", error);
         process::exit(1);
     });
 
-    let mut contents = String::new();
-    crontab_file.read_to_string(&mut contents).unwrap();
-
-    let crontab = Crontab::parse(&contents).unwrap_or_else(|error| {
-        error!("error parsing crontab: {}", error);
-        process::exit(1);
-    });
-    debug!("parsed crontab {:?}", crontab);
+    let pidfile = match &config.pidfile {
+        Some(path) => Some(Pidfile::acquire(Path::new(path)).unwrap_or_else(|error| {
+            error!("{}", error);
+            process::exit(1);
+        })),
+        None => {
+            warn!("running without a pidfile, set CRONDY_PIDFILE to keep out a second crondy");
+            None
+        }
+    };
 
-    crontab.validate().unwrap_or_else(|error| {
-        error!("error validating crontab: {}", error);
+    let owner = User::current().unwrap_or_else(|error| {
+        error!("could not find out who we are running as: {}", error);
         process::exit(1);
     });
-    debug!("validated crontab");
+    debug!("running jobs as {}", owner.name);
 
     // don't forget to check DST!!!
-    let mut clock_time = SystemTime::now();
+    let mut clock_time = clock::now();
+
+    debug!("job limits: {:?}", config.limits);
+    let mut executor = Executor::new(owner, config.limits);
+    debug!("running reboot jobs");
+    for entry in crontab.reboot_jobs() {
+        executor.run(entry, clock_time);
+    }
+
+    let state_dir = config.state_dir.as_deref().map(Path::new);
+    let history = match state_dir {
+        Some(state_dir) => match fs::create_dir_all(state_dir) {
+            Ok(()) => Some(History::new(&state_dir.join("history"))),
+            Err(error) => {
+                error!(
+                    "could not create state directory {}, not keeping run history: {}",
+                    state_dir.display(),
+                    error
+                );
+                None
+            }
+        },
+        None => {
+            warn!(
+                "not keeping run history or catching up on missed runs, \
+                 set CRONDY_STATE_DIR to somewhere to keep them"
+            );
+            None
+        }
+    };
+
+    let mut last_runs = state_dir.and_then(load_last_runs);
+    if let Some(last_runs) = &mut last_runs {
+        for entry in crontab.entries() {
+            if entry.options().catch_up == 0 {
+                continue;
+            }
+            let missed = last_runs.missed(entry, clock_time);
+            if !missed.is_empty() {
+                event!(
+                    Level::Info,
+                    Event {
+                        kind: "catching_up",
+                        entry: Some(entry),
+                        scheduled: missed.first().cloned(),
+                        ..Event::default()
+                    },
+                    "catching up on {} missed runs of {} ({})",
+                    missed.len(),
+                    entry.location(),
+                    entry.command()
+                );
+                for &scheduled in &missed {
+                    executor.run(entry, scheduled);
+                }
+            }
+            // so that whatever is missed from now on can be made up for
+            if !missed.is_empty() || last_runs.get(entry).is_none() {
+                if let Err(error) = last_runs.record(entry, clock_time) {
+                    error!("could not record run of {}: {}", entry.location(), error);
+                }
+            }
+        }
+    }
+    executor.run_queue();
 
-    crontab.run_reboot_jobs();
+    let control = match &config.socket {
+        Some(socket) => match Server::bind(Path::new(socket)) {
+            Ok(server) => {
+                debug!("listening on {}", socket);
+                Some(server)
+            }
+            Err(error) => {
+                error!("{}, running without a control socket", error);
+                None
+            }
+        },
+        None => {
+            warn!("running without a control socket, set CRONDY_SOCKET to have one");
+            None
+        }
+    };
+    let mut paused = Paused::default();
+
+    let metrics_file = &config.metrics_file;
+    if let Some(path) = metrics_file {
+        if let Err(error) = executor.metrics().write_textfile(Path::new(path)) {
+            error!("{}", error);
+        }
+    }
+    if let Some(address) = &config.metrics_address {
+        match executor.metrics().serve(address) {
+            Ok(address) => debug!("serving metrics on {}", address),
+            Err(error) => error!("{}, not serving metrics", error),
+        }
+    }
+
+    if let Some(daemon) = daemon {
+        if let Err(error) = daemon.ready() {
+            error!("{}", error);
+            process::exit(1);
+        }
+        debug!("running in the background as pid {}", process::id());
+    }
+
+    let mut scheduler = Scheduler::new(clock_time);
+    'schedule: loop {
+        /*
+         * ... wait for the time (in minutes) to change ...
+         * Jobs need looking after in the meantime, so doze a second at a
+         * time rather than sleeping through to the next minute.
+         */
+        while clock_time == scheduler.time_running() {
+            thread::sleep(Duration::from_secs(1));
+            if stop.load(Ordering::SeqCst) != 0 {
+                break 'schedule;
+            }
+            if child_died.swap(false, Ordering::SeqCst) {
+                reap(&mut executor, &history);
+            }
+            if reload.swap(false, Ordering::SeqCst) {
+                debug!("got SIGHUP");
+                let _ = reload_crontab(&mut crontab, &config.crontabs, &mut executor);
+            }
+            if let Some(control) = &control {
+                while let Some(request) = control.accept() {
+                    let result = match &request.command {
+                        Ok(command) => handle_command(
+                            command,
+                            &mut crontab,
+                            &config.crontabs,
+                            &mut executor,
+                            &mut paused,
+                            clock_time,
+                        ),
+                        Err(error) => Err(format_err!("{}", error)),
+                    };
+                    request.reply(result);
+                }
+            }
+            executor.enforce_timeouts();
+            executor.run_due_retries();
+            executor.run_queue();
+            write_metrics(&executor, metrics_file);
+            clock_time = clock::now();
+        }
+
+        let (_, now) = scheduler.advance(clock_time, &mut clock::now, &mut |minute, wild, fixed| {
+            find_jobs(&crontab, minute, wild, fixed, &mut executor, &mut last_runs, &paused)
+        });
+        clock_time = now;
+
+        // jobs to be run (if any) are loaded. clear the queue
+        executor.run_queue();
+    }
 
-    let mut time_running = clock_time;
-    let mut virtual_time = clock_time;
     /*
-     * too many clocks, not enough time (Al. Einstein)
-     * These clocks are in minutes since the epoch (time()/60).
-     * virtual_time: is the time it *would* be if we woke up
-     * promptly and nobody ever changed the clock. It is
-     * monotonically increasing... unless a timejump happens.
-     * At the top of the loop, all jobs for 'virtual_time' have run.
-     * time_running: is the time we last awakened.
-     * clock_time: is the time when set_time was last called.
+     * Asked to stop: start nothing more, give the jobs that are running
+     * until the deadline to finish, and leave the rest behind.
      */
-    loop {
-        //		time_min timeDiff;
-        //		int wakeupKind;
-        //
-        //		/* ... wait for the time (in minutes) to change ... */
-        //		do {
-        //			cron_sleep(timeRunning + 1);
-        //			set_time(FALSE);
-        //		} while (clockTime == timeRunning);
-        //		timeRunning = clockTime;
-        let target = time_running.duration_since(SystemTime::UNIX_EPOCH) + one_minute;
-        let wait = (target - SystemTime::now().duration_since(SystemTime::UNIX_EPOCH)) + one_second;
-        debug!("sleeping (target: {}, wait: {})", target, wait);
-        thread::sleep(wait);
-        //
-        //		check_orphans(&database);
-        //		load_database(&database);
-        //
-        //		/*
-        //		 * ... calculate how the current time differs from
-        //		 * our virtual clock. Classify the change into one
-        //		 * of 4 cases
-        //		 */
-        //		timeDiff = timeRunning - virtualTime;
-        //
-        //		Debug(DSCH, ("[%d] pulse: %d = %d - %d\n",
-        //            	    getpid(), timeDiff, timeRunning, virtualTime));
-        //
-        //		/* shortcut for the most common case */
-        //		if (timeDiff == 1) {
-        //			virtualTime = timeRunning;
-        //			find_jobs(virtualTime, &database, TRUE, TRUE);
-        //		} else {
-        //			wakeupKind = -1;
-        //			if (timeDiff > -(3*MINUTE_COUNT))
-        //				wakeupKind = 0;
-        //			if (timeDiff > 0)
-        //				wakeupKind = 1;
-        //			if (timeDiff > 5)
-        //				wakeupKind = 2;
-        //			if (timeDiff > (3*MINUTE_COUNT))
-        //				wakeupKind = 3;
-        //
-        //			switch (wakeupKind) {
-        //			case 1:
-        //				/*
-        //				 * case 1: timeDiff is a small positive number
-        //				 * (wokeup late) run jobs for each virtual minute
-        //				 * until caught up.
-        //				 */
-        //				Debug(DSCH, ("[%d], normal case %d minutes to go\n",
-        //				    getpid(), timeRunning - virtualTime))
-        //				do {
-        //					if (job_runqueue())
-        //						sleep(10);
-        //					virtualTime++;
-        //					find_jobs(virtualTime, &database, TRUE, TRUE);
-        //				} while (virtualTime< timeRunning);
-        //				break;
-        //
-        //			case 2:
-        //				/*
-        //				 * case 2: timeDiff is a medium-sized positive number,
-        //				 * for example because we went to DST run wildcard
-        //				 * jobs once, then run any fixed-time jobs that would
-        //				 * otherwise be skipped if we use up our minute
-        //				 * (possible, if there are a lot of jobs to run) go
-        //				 * around the loop again so that wildcard jobs have
-        //				 * a chance to run, and we do our housekeeping
-        //				 */
-        //				Debug(DSCH, ("[%d], DST begins %d minutes to go\n",
-        //				    getpid(), timeRunning - virtualTime))
-        //				/* run wildcard jobs for current minute */
-        //				find_jobs(timeRunning, &database, TRUE, FALSE);
-        //
-        //				/* run fixed-time jobs for each minute missed */
-        //				do {
-        //					if (job_runqueue())
-        //						sleep(10);
-        //					virtualTime++;
-        //					find_jobs(virtualTime, &database, FALSE, TRUE);
-        //					set_time(FALSE);
-        //				} while (virtualTime< timeRunning &&
-        //				    clockTime == timeRunning);
-        //				break;
-        //
-        //			case 0:
-        //				/*
-        //				 * case 3: timeDiff is a small or medium-sized
-        //				 * negative num, eg. because of DST ending just run
-        //				 * the wildcard jobs. The fixed-time jobs probably
-        //				 * have already run, and should not be repeated
-        //				 * virtual time does not change until we are caught up
-        //				 */
-        //				Debug(DSCH, ("[%d], DST ends %d minutes to go\n",
-        //				    getpid(), virtualTime - timeRunning))
-        //				find_jobs(timeRunning, &database, TRUE, FALSE);
-        //				break;
-        //			default:
-        //				/*
-        //				 * other: time has changed a *lot*,
-        //				 * jump virtual time, and run everything
-        //				 */
-        //				Debug(DSCH, ("[%d], clock jumped\n", getpid()))
-        //				virtualTime = timeRunning;
-        //				find_jobs(timeRunning, &database, TRUE, TRUE);
-        //			}
-        //		}
-        //		/* jobs to be run (if any) are loaded. clear the queue */
-        //		job_runqueue();
+    let signal = stop.load(Ordering::SeqCst) as libc::c_int;
+    event!(
+        Level::Info,
+        Event {
+            kind: "crondy_stopping",
+            ..Event::default()
+        },
+        "got signal {}, shutting down",
+        signal
+    );
+    drop(control);
+    let dropped = executor.stop_starting();
+    if dropped > 0 {
+        info!("not starting {} runs that were waiting", dropped);
+    }
+    let running = executor.jobs();
+    if !running.is_empty() {
+        if config.forward_signals {
+            info!("passing signal {} on to {} running jobs", signal, running.len());
+            executor.signal_all(signal);
+        }
+        info!(
+            "waiting up to {}s for {} running jobs",
+            config.shutdown_timeout.as_secs(),
+            running.len()
+        );
+    }
+    // too long to count is as good as no deadline at all
+    let deadline = Instant::now().checked_add(config.shutdown_timeout);
+    let before_deadline = || deadline.is_none_or(|deadline| Instant::now() < deadline);
+    while !executor.jobs().is_empty() && before_deadline() {
+        thread::sleep(Duration::from_millis(100));
+        if child_died.swap(false, Ordering::SeqCst) {
+            reap(&mut executor, &history);
+        }
+        executor.enforce_timeouts();
+    }
+    write_metrics(&executor, metrics_file);
+    let left = executor.jobs();
+    for job in &left {
+        warn!(
+            "leaving job {} for {} running (attempt {}, since {}): {}",
+            job.pid,
+            job.location,
+            job.attempt,
+            clock::format_seconds(job.started),
+            job.command
+        );
+    }
+    event!(
+        Level::Info,
+        Event {
+            kind: "crondy_stopped",
+            ..Event::default()
+        },
+        "stopped, leaving {} jobs running",
+        left.len()
+    );
+    // held until now, so that no other crondy starts while ours still run
+    drop(pidfile);
+    process::exit(if left.is_empty() { 0 } else { 1 });
+}
+
+/// Collect the jobs that have exited and keep a record of them.
+fn reap(executor: &mut Executor, history: &Option<History>) {
+    for run in executor.reap() {
+        if let Some(history) = history {
+            if let Err(error) = history.append(&run) {
+                error!("could not record run of line {}: {}", run.line, error);
+            }
+        }
+    }
+}
+
+fn write_metrics(executor: &Executor, metrics_file: &Option<String>) {
+    if let Some(path) = metrics_file {
+        if executor.metrics().take_changed() {
+            if let Err(error) = executor.metrics().write_textfile(Path::new(path)) {
+                error!("{}", error);
+            }
+        }
+    }
+}
+
+fn find_jobs(
+    crontab: &Crontab,
+    minute: clock::Minute,
+    do_wild: bool,
+    do_non_wild: bool,
+    executor: &mut Executor,
+    last_runs: &mut Option<LastRuns>,
+    paused: &Paused,
+) {
+    for entry in crontab.find_jobs(&Moment::at(minute), do_wild, do_non_wild) {
+        if paused.is_paused(entry) {
+            debug!("{} is paused, not running it", entry.location());
+            executor.metrics().missed(entry);
+        } else {
+            executor.run(entry, minute);
+        }
+        // a paused entry has nothing to catch up on once it is resumed
+        if let Some(last_runs) = last_runs {
+            if entry.options().catch_up > 0 {
+                if let Err(error) = last_runs.record(entry, minute) {
+                    error!("could not record run of {}: {}", entry.location(), error);
+                }
+            }
+        }
+    }
+}
+
+/*
+ * Read all the crontabs at `paths`, where a directory stands for the files
+ * in it, in order of their names. Hidden files and editor backups (`~`) in
+ * directories are left alone.
+ */
+fn load_crontabs(paths: &[String]) -> Result<Crontab, Error> {
+    let mut crontabs = Vec::new();
+    for path in paths {
+        if !Path::new(path).is_dir() {
+            crontabs.push(load_crontab(path)?);
+            continue;
+        }
+        let mut files = Vec::new();
+        let entries = fs::read_dir(path)
+            .map_err(|error| format_err!("could not read directory {}: {}", path, error))?;
+        for entry in entries {
+            let entry =
+                entry.map_err(|error| format_err!("could not read directory {}: {}", path, error))?;
+            let name = entry.file_name().to_string_lossy().into_owned();
+            if name.starts_with('.') || name.ends_with('~') || entry.path().is_dir() {
+                continue;
+            }
+            files.push(entry.path().to_string_lossy().into_owned());
+        }
+        files.sort();
+        for file in files {
+            crontabs.push(load_crontab(&file)?);
+        }
+    }
+    Ok(Crontab::merge(crontabs))
+}
+
+fn load_crontab(path: &str) -> Result<Crontab, Error> {
+    let crontab = Crontab::parse(&read_crontab(path)?)
+        .map_err(|error| format_err!("error parsing crontab {}: {}", path, error))?
+        .in_file(path);
+    debug!("parsed crontab {:?}", crontab);
+    crontab
+        .validate()
+        .map_err(|error| format_err!("error validating crontab {}: {}", path, error))?;
+    debug!("validated crontab");
+    Ok(crontab)
+}
+
+/*
+ * Carry out a request that came in over the control socket, and say what
+ * there is to say about it.
+ */
+fn handle_command(
+    command: &Command,
+    crontab: &mut Crontab,
+    crontab_paths: &[String],
+    executor: &mut Executor,
+    paused: &mut Paused,
+    now: Minute,
+) -> Result<Vec<String>, Error> {
+    let entry = |target: &Target| target.find(crontab.entries()).cloned();
+    fn event<'a>(kind: &'static str, entry: Option<&'a Entry>) -> Event<'a> {
+        Event {
+            kind,
+            entry,
+            ..Event::default()
+        }
+    }
+    let mut reply = Vec::new();
+    match command {
+        Command::Entries => {
+            for entry in crontab.entries() {
+                let next = match entry.schedule() {
+                    Schedule::Reboot => "@reboot".to_owned(),
+                    schedule => match schedule.next_after(now) {
+                        Some(next) => Moment::at(next).to_string(),
+                        None => "never".to_owned(),
+                    },
+                };
+                reply.push(format!(
+                    "{} {} {}{}",
+                    entry.location(),
+                    next,
+                    entry.source(),
+                    if paused.is_paused(entry) { " (paused)" } else { "" }
+                ));
+            }
+        }
+        Command::Jobs => {
+            for job in executor.jobs() {
+                reply.push(format!(
+                    "{} {} attempt {} since {}: {}",
+                    job.pid,
+                    job.location,
+                    job.attempt,
+                    clock::format_seconds(job.started),
+                    job.command
+                ));
+            }
+        }
+        Command::Run(target) => {
+            let entry = entry(target)?;
+            event!(
+                Level::Info,
+                event("run_requested", Some(&entry)),
+                "running {} ({}) on request",
+                entry.location(),
+                entry.command()
+            );
+            executor.run(&entry, now);
+            executor.run_queue();
+        }
+        Command::Pause(Some(target)) => {
+            let entry = entry(target)?;
+            paused.entries.insert(entry.source().to_owned());
+            event!(
+                Level::Info,
+                event("entry_paused", Some(&entry)),
+                "paused {}",
+                entry.location()
+            );
+        }
+        Command::Pause(None) => {
+            paused.scheduler = true;
+            event!(Level::Info, event("scheduler_paused", None), "paused the scheduler");
+        }
+        Command::Resume(Some(target)) => {
+            let entry = entry(target)?;
+            paused.entries.remove(entry.source());
+            event!(
+                Level::Info,
+                event("entry_resumed", Some(&entry)),
+                "resumed {}",
+                entry.location()
+            );
+        }
+        Command::Resume(None) => {
+            paused.scheduler = false;
+            event!(Level::Info, event("scheduler_resumed", None), "resumed the scheduler");
+        }
+        Command::Reload => reload_crontab(crontab, crontab_paths, executor)?,
+    }
+    Ok(reply)
+}
+
+/// Read the crontabs again, keeping the ones we have if the new ones are no
+/// good.
+fn reload_crontab(
+    crontab: &mut Crontab,
+    crontab_paths: &[String],
+    executor: &mut Executor,
+) -> Result<(), Error> {
+    let event = |kind| Event {
+        kind,
+        ..Event::default()
+    };
+    let reloaded = load_crontabs(crontab_paths);
+    executor.metrics().reloaded(reloaded.is_ok());
+    match reloaded {
+        Ok(reloaded) => {
+            *crontab = reloaded;
+            event!(
+                Level::Info,
+                event("crontab_reloaded"),
+                "reloaded {}",
+                crontab_paths.join(", ")
+            );
+            Ok(())
+        }
+        Err(error) => {
+            event!(
+                Level::Error,
+                event("crontab_reload_failed"),
+                "could not reload: {}",
+                error
+            );
+            Err(error)
+        }
+    }
+}
+
+/*
+ * `crondy history [--entry LINE|TEXT] [--since TIME] [--until TIME]
+ * [--outcome OUTCOME]` prints the runs on record, oldest first.
+ */
+fn show_history(config: &Config, args: &[String]) -> Result<String, Error> {
+    let filter = Filter::parse(args)?;
+    let state_dir = match &config.state_dir {
+        Some(state_dir) => Path::new(state_dir),
+        None => bail!("no state directory to find the history in, set CRONDY_STATE_DIR"),
+    };
+    let mut output = String::new();
+    for run in History::new(&state_dir.join("history")).read()? {
+        if filter.matches(&run) {
+            output.push_str(&history::describe(&run));
+            output.push('\n');
+        }
+    }
+    Ok(output)
+}
+
+/*
+ * `crondy next` shows when the entries in a crontab are next due; see
+ * next.rs for the options.
+ */
+fn show_next(config: &Config, args: &[String]) -> Result<String, Error> {
+    let query = Query::parse(args)?;
+    if let Some(timezone) = &query.timezone {
+        set_timezone(timezone)?;
+    }
+    let crontab = match &query.crontab {
+        Some(path) => Crontab::parse(&read_crontab(path)?)?,
+        None => load_crontabs(&config.crontabs)?,
+    };
+    crontab.validate()?;
+    let from = query.from.unwrap_or_else(clock::now);
+    Ok(if query.json {
+        next::json(&crontab, from, &query)
+    } else {
+        next::plain(&crontab, from, &query)
+    })
+}
+
+/*
+ * `crondy simulate` shows what the scheduler would start over a stretch of
+ * time, without starting anything; see simulate.rs for the options.
+ */
+fn simulate(config: &Config, args: &[String]) -> Result<String, Error> {
+    let simulation = Simulation::parse(args)?;
+    if let Some(timezone) = &simulation.timezone {
+        set_timezone(timezone)?;
+    }
+    let crontab = match &simulation.crontab {
+        Some(path) => Crontab::parse(&read_crontab(path)?)?,
+        None => load_crontabs(&config.crontabs)?,
+    };
+    crontab.validate()?;
+    Ok(simulation.run(&crontab, &clock::wall_clock))
+}
+
+extern "C" {
+    fn tzset();
+}
+
+/*
+ * Make the local wall clock that of `timezone`, a name from the tz database
+ * like `Europe/Berlin` or a POSIX TZ string like `CET-1CEST,M3.5.0,M10.5.0/3`.
+ * glibc quietly takes anything it doesn't know for UTC, so names are checked
+ * against the database first. This changes the environment, which is only
+ * safe before any threads are started: `next` and `simulate` call it before
+ * doing anything else, and the daemon never does.
+ */
+fn set_timezone(timezone: &str) -> Result<(), Error> {
+    let name = timezone.trim_start_matches(':');
+    let posix = name.contains(|c: char| c.is_ascii_digit()) && !name.contains('/');
+    let known = name == "UTC" || Path::new("/usr/share/zoneinfo").join(name).is_file();
+    if name.is_empty() || name.contains("..") || !(posix || known) {
+        bail!("unknown timezone {:?}", timezone);
+    }
+    std::env::set_var("TZ", timezone);
+    unsafe { tzset() };
+    Ok(())
+}
+
+/*
+ * `crondy check [--system] [CRONTAB...]` reports everything wrong with
+ * the crontabs (or stdin) as FILE:LINE: error|warning: MESSAGE, and exits
+ * with 0 if there was nothing to report, 1 if there were errors and 2 if
+ * there were only warnings. --system expects a user name before each
+ * command, as in /etc/crontab.
+ */
+fn check(args: &[String]) -> Result<(String, i32), Error> {
+    let mut syntax = Syntax::User;
+    let mut paths = Vec::new();
+    for arg in args {
+        match arg.as_str() {
+            "--system" => syntax = Syntax::System,
+            "--user" => syntax = Syntax::User,
+            _ if arg.starts_with("--") => bail!("unknown option {:?}", arg),
+            _ => paths.push(arg.as_str()),
+        }
+    }
+    if paths.is_empty() {
+        paths.push("-");
+    }
+    let mut output = String::new();
+    let mut worst = None;
+    for path in paths {
+        let name = if path == "-" { "<stdin>" } else { path };
+        let problems = match read_crontab(path) {
+            Ok(contents) => Crontab::check(&contents, syntax),
+            Err(error) => vec![Problem {
+                line: 0,
+                severity: Severity::Error,
+                message: error.to_string(),
+            }],
+        };
+        for problem in problems {
+            let severity = match problem.severity {
+                Severity::Error => "error",
+                Severity::Warning => "warning",
+            };
+            let location = match problem.line {
+                0 => name.to_owned(),
+                line => format!("{}:{}", name, line),
+            };
+            output.push_str(&format!("{}: {}: {}\n", location, severity, problem.message));
+            worst = worst.max(Some(problem.severity));
+        }
+    }
+    let status = match worst {
+        None => 0,
+        Some(Severity::Error) => 1,
+        Some(Severity::Warning) => 2,
+    };
+    Ok((output, status))
+}
+
+/// The contents of the crontab at `path`, or of stdin for `-`.
+fn read_crontab(path: &str) -> Result<String, Error> {
+    let mut contents = String::new();
+    if path == "-" {
+        std::io::stdin().read_to_string(&mut contents)?;
+    } else {
+        File::open(path)
+            .and_then(|mut file| file.read_to_string(&mut contents))
+            .map_err(|error| format_err!("could not read crontab {}: {}", path, error))?;
+    }
+    Ok(contents)
+}
+
+/*
+ * Without the store, entries can't catch up on missed runs, but everything
+ * else works, so trouble with it is not fatal.
+ */
+fn load_last_runs(state_dir: &Path) -> Option<LastRuns> {
+    if let Err(error) = fs::create_dir_all(state_dir) {
+        error!(
+            "could not create state directory {}, not catching up on missed runs: {}",
+            state_dir.display(),
+            error
+        );
+        return None;
+    }
+    match LastRuns::load(&state_dir.join("lastrun")) {
+        Ok(last_runs) => Some(last_runs),
+        Err(error) => {
+            error!("{}, not catching up on missed runs", error);
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
 
+    #[test]
+    fn refuses_unknown_timezones() {
+        assert!(set_timezone("Mars/Olympus_Mons").is_err());
+        assert!(set_timezone("../../etc/passwd").is_err());
+        assert!(set_timezone("").is_err());
     }
 }
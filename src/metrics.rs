@@ -0,0 +1,481 @@
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::fs;
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+use crondy::crontab::Entry;
+use failure::{bail, format_err, Error};
+use log::{debug, warn};
+
+use crate::executor::Finished;
+use crate::job::Outcome;
+
+/// Upper bounds of the run duration buckets, in seconds.
+const DURATION_BUCKETS: &[f64] = &[
+    1.0, 5.0, 15.0, 60.0, 300.0, 900.0, 1800.0, 3600.0, 7200.0, 21600.0, 86400.0,
+];
+/// Upper bounds of the scheduling lag buckets, in seconds.
+const LAG_BUCKETS: &[f64] = &[1.0, 2.0, 5.0, 15.0, 30.0, 60.0, 120.0, 300.0, 900.0, 3600.0];
+
+/*
+ * Counters and histograms about what crondy has been doing, for Prometheus.
+ * Entries are told apart by how they are written in the crontab. A handle
+ * is cheap to clone and all clones count together.
+ *
+ * A run counts as missed when it was due but not started, because the
+ * entry was paused or CRONDY_OVERLAP=skip found it still running. Failed
+ * runs are those that exited with another status than 0 or were killed by
+ * a signal; runs that timed out are counted separately.
+ */
+#[derive(Debug, Clone, Default)]
+pub struct Metrics {
+    inner: Arc<Mutex<Registry>>,
+}
+
+#[derive(Debug, Default)]
+struct Registry {
+    entries: BTreeMap<String, EntryMetrics>,
+    reloads: u64,
+    failed_reloads: u64,
+    changed: bool,
+}
+
+#[derive(Debug)]
+struct EntryMetrics {
+    started: u64,
+    succeeded: u64,
+    failed: u64,
+    timed_out: u64,
+    missed: u64,
+    duration: Histogram,
+    lag: Histogram,
+    last_success: Option<u64>, // seconds since the epoch, in UTC
+}
+
+impl Default for EntryMetrics {
+    fn default() -> Self {
+        EntryMetrics {
+            started: 0,
+            succeeded: 0,
+            failed: 0,
+            timed_out: 0,
+            missed: 0,
+            duration: Histogram::new(DURATION_BUCKETS),
+            lag: Histogram::new(LAG_BUCKETS),
+            last_success: None,
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Histogram {
+    bounds: &'static [f64],
+    counts: Vec<u64>, // per bucket, not cumulative
+    sum: f64,
+    count: u64,
+}
+
+impl Histogram {
+    fn new(bounds: &'static [f64]) -> Self {
+        Histogram {
+            bounds,
+            counts: vec![0; bounds.len()],
+            sum: 0.0,
+            count: 0,
+        }
+    }
+
+    fn observe(&mut self, value: f64) {
+        if let Some(bucket) = self.bounds.iter().position(|bound| value <= *bound) {
+            self.counts[bucket] += 1;
+        }
+        self.sum += value;
+        self.count += 1;
+    }
+}
+
+impl Metrics {
+    fn update(&self, change: impl FnOnce(&mut Registry)) {
+        let mut registry = self.inner.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        change(&mut registry);
+        registry.changed = true;
+    }
+
+    fn entry<'a>(registry: &'a mut Registry, source: &str) -> &'a mut EntryMetrics {
+        registry.entries.entry(source.to_owned()).or_default()
+    }
+
+    /// A run of `entry` started, `lag` seconds after the minute it was due
+    /// if it was on time as far as it could be.
+    pub fn started(&self, entry: &Entry, lag: Option<i64>) {
+        self.update(|registry| {
+            let metrics = Self::entry(registry, entry.source());
+            metrics.started += 1;
+            if let Some(lag) = lag {
+                metrics.lag.observe(lag.max(0) as f64);
+            }
+        });
+    }
+
+    pub fn finished(&self, run: &Finished) {
+        let now = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map(|since| since.as_secs())
+            .unwrap_or_default();
+        self.update(|registry| {
+            let metrics = Self::entry(registry, &run.source);
+            match run.outcome {
+                Outcome::Exited(0) => {
+                    metrics.succeeded += 1;
+                    metrics.last_success = Some(now);
+                }
+                Outcome::TimedOut => metrics.timed_out += 1,
+                _ => metrics.failed += 1,
+            }
+            metrics.duration.observe((run.ended - run.started).max(0) as f64);
+        });
+    }
+
+    pub fn missed(&self, entry: &Entry) {
+        self.update(|registry| Self::entry(registry, entry.source()).missed += 1);
+    }
+
+    pub fn reloaded(&self, succeeded: bool) {
+        self.update(|registry| {
+            if succeeded {
+                registry.reloads += 1;
+            } else {
+                registry.failed_reloads += 1;
+            }
+        });
+    }
+
+    /// Whether anything was counted since the last time this was asked.
+    pub fn take_changed(&self) -> bool {
+        let mut registry = self.inner.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        std::mem::replace(&mut registry.changed, false)
+    }
+
+    /// Everything, in the Prometheus text format.
+    pub fn render(&self) -> String {
+        let registry = self.inner.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        let mut out = String::new();
+        let entries = &registry.entries;
+        counter(&mut out, entries, "crondy_jobs_started_total", "Runs started.", |m| m.started);
+        counter(
+            &mut out,
+            entries,
+            "crondy_jobs_succeeded_total",
+            "Runs that exited with status 0.",
+            |m| m.succeeded,
+        );
+        counter(
+            &mut out,
+            entries,
+            "crondy_jobs_failed_total",
+            "Runs that failed, other than by timing out.",
+            |m| m.failed,
+        );
+        counter(
+            &mut out,
+            entries,
+            "crondy_jobs_timed_out_total",
+            "Runs stopped for running past their timeout.",
+            |m| m.timed_out,
+        );
+        counter(
+            &mut out,
+            entries,
+            "crondy_missed_runs_total",
+            "Runs that were due but not started.",
+            |m| m.missed,
+        );
+        histogram(
+            &mut out,
+            entries,
+            "crondy_job_duration_seconds",
+            "How long runs took.",
+            |m| &m.duration,
+        );
+        histogram(
+            &mut out,
+            entries,
+            "crondy_scheduling_lag_seconds",
+            "How long after the minute they were due runs started.",
+            |m| &m.lag,
+        );
+
+        let name = "crondy_last_success_timestamp_seconds";
+        header(&mut out, name, "When a run last succeeded.", "gauge");
+        for (source, metrics) in &registry.entries {
+            if let Some(last_success) = metrics.last_success {
+                let _ = writeln!(out, "{}{{entry={}}} {}", name, label(source), last_success);
+            }
+        }
+
+        let name = "crondy_crontab_reloads_total";
+        header(&mut out, name, "Crontab reloads.", "counter");
+        let _ = writeln!(out, "{}{{result=\"success\"}} {}", name, registry.reloads);
+        let _ = writeln!(out, "{}{{result=\"failure\"}} {}", name, registry.failed_reloads);
+        out
+    }
+
+    /*
+     * Write the metrics to `path` for node_exporter's textfile collector,
+     * which must never see a half-written file.
+     */
+    pub fn write_textfile(&self, path: &Path) -> Result<(), Error> {
+        let temporary = path.with_extension("prom.new");
+        fs::write(&temporary, self.render())
+            .and_then(|_| fs::rename(&temporary, path))
+            .map_err(|error| format_err!("could not write {}: {}", path.display(), error))
+    }
+
+    /*
+     * Serve the metrics over HTTP on `address`, from a thread of its own.
+     * Only loopback addresses are allowed: anything further away should go
+     * through node_exporter.
+     */
+    pub fn serve(&self, address: &str) -> Result<SocketAddr, Error> {
+        let address: SocketAddr = address.parse().map_err(|_| {
+            format_err!("expected an address like 127.0.0.1:9745, got {:?}", address)
+        })?;
+        if !address.ip().is_loopback() {
+            bail!("will only serve metrics on a loopback address, not {}", address.ip());
+        }
+        let listener = TcpListener::bind(address)
+            .map_err(|error| format_err!("could not listen on {}: {}", address, error))?;
+        let address = listener.local_addr()?;
+        let metrics = self.clone();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                match stream {
+                    Ok(stream) => {
+                        if let Err(error) = metrics.answer(stream) {
+                            debug!("could not answer metrics request: {}", error);
+                        }
+                    }
+                    Err(error) => warn!("could not accept metrics connection: {}", error),
+                }
+            }
+        });
+        Ok(address)
+    }
+
+    fn answer(&self, mut stream: TcpStream) -> io::Result<()> {
+        stream.set_read_timeout(Some(Duration::from_secs(5)))?;
+        stream.set_write_timeout(Some(Duration::from_secs(5)))?;
+        let mut reader = BufReader::new(&stream);
+        let mut request = String::new();
+        reader.read_line(&mut request)?;
+        // the headers don't matter, but have to be read
+        let mut header = String::new();
+        while reader.read_line(&mut header)? > 2 {
+            header.clear();
+        }
+        let mut words = request.split_whitespace();
+        let response = match (words.next(), words.next()) {
+            (Some("GET"), Some("/metrics")) => {
+                let body = self.render();
+                format!(
+                    "HTTP/1.0 200 OK\r\n\
+                     Content-Type: text/plain; version=0.0.4\r\n\
+                     Content-Length: {}\r\n\r\n{}",
+                    body.len(),
+                    body
+                )
+            }
+            _ => "HTTP/1.0 404 Not Found\r\nContent-Length: 0\r\n\r\n".to_owned(),
+        };
+        stream.write_all(response.as_bytes())
+    }
+}
+
+fn header(out: &mut String, name: &str, help: &str, kind: &str) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+}
+
+fn counter(
+    out: &mut String,
+    entries: &BTreeMap<String, EntryMetrics>,
+    name: &str,
+    help: &str,
+    value: fn(&EntryMetrics) -> u64,
+) {
+    header(out, name, help, "counter");
+    for (source, metrics) in entries {
+        let _ = writeln!(out, "{}{{entry={}}} {}", name, label(source), value(metrics));
+    }
+}
+
+fn histogram(
+    out: &mut String,
+    entries: &BTreeMap<String, EntryMetrics>,
+    name: &str,
+    help: &str,
+    histogram: fn(&EntryMetrics) -> &Histogram,
+) {
+    header(out, name, help, "histogram");
+    for (source, metrics) in entries {
+        let entry = label(source);
+        let histogram = histogram(metrics);
+        let mut cumulative = 0;
+        for (bound, count) in histogram.bounds.iter().zip(&histogram.counts) {
+            cumulative += count;
+            let _ = writeln!(
+                out,
+                "{}_bucket{{entry={},le=\"{}\"}} {}",
+                name, entry, bound, cumulative
+            );
+        }
+        let _ = writeln!(out, "{}_bucket{{entry={},le=\"+Inf\"}} {}", name, entry, histogram.count);
+        let _ = writeln!(out, "{}_sum{{entry={}}} {}", name, entry, histogram.sum);
+        let _ = writeln!(out, "{}_count{{entry={}}} {}", name, entry, histogram.count);
+    }
+}
+
+/// A label value, quoted and escaped.
+fn label(value: &str) -> String {
+    let mut quoted = String::from("\"");
+    for c in value.chars() {
+        match c {
+            '\\' => quoted.push_str("\\\\"),
+            '"' => quoted.push_str("\\\""),
+            '\n' => quoted.push_str("\\n"),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_files::temporary;
+    use crondy::crontab::Crontab;
+    use std::io::Read;
+
+    fn run(source: &str, outcome: Outcome, took: i64) -> Finished {
+        Finished {
+            line: 1,
+            source: source.to_owned(),
+            pid: 1,
+            scheduled: 0,
+            attempt: 1,
+            started: 0,
+            ended: took,
+            outcome,
+            output: 0,
+        }
+    }
+
+    fn lines(metrics: &Metrics, prefix: &str) -> Vec<String> {
+        metrics
+            .render()
+            .lines()
+            .filter(|line| line.starts_with(prefix))
+            .map(str::to_owned)
+            .collect()
+    }
+
+    #[test]
+    fn counts_runs() {
+        let tab = Crontab::parse("@daily backup \"all\"").unwrap();
+        let entry = &tab.entries()[0];
+        let metrics = Metrics::default();
+        metrics.started(entry, Some(3));
+        metrics.started(entry, Some(3));
+        metrics.started(entry, Some(3));
+        metrics.finished(&run(entry.source(), Outcome::Exited(0), 10));
+        metrics.finished(&run(entry.source(), Outcome::Signaled(9), 10));
+        metrics.finished(&run(entry.source(), Outcome::TimedOut, 10));
+        metrics.missed(entry);
+
+        let label = "{entry=\"@daily backup \\\"all\\\"\"}";
+        assert_eq!(
+            lines(&metrics, "crondy_jobs_"),
+            vec![
+                format!("crondy_jobs_started_total{} 3", label),
+                format!("crondy_jobs_succeeded_total{} 1", label),
+                format!("crondy_jobs_failed_total{} 1", label),
+                format!("crondy_jobs_timed_out_total{} 1", label),
+            ]
+        );
+        assert_eq!(
+            lines(&metrics, "crondy_missed"),
+            vec![format!("crondy_missed_runs_total{} 1", label)]
+        );
+        assert_eq!(lines(&metrics, "crondy_last_success").len(), 1);
+    }
+
+    #[test]
+    fn fills_histogram_buckets() {
+        let metrics = Metrics::default();
+        metrics.finished(&run("x", Outcome::Exited(0), 3));
+        metrics.finished(&run("x", Outcome::Exited(0), 100_000));
+        let duration = lines(&metrics, "crondy_job_duration_seconds");
+        for expected in &[
+            "_bucket{entry=\"x\",le=\"1\"} 0",
+            "_bucket{entry=\"x\",le=\"5\"} 1",
+            "_bucket{entry=\"x\",le=\"86400\"} 1",
+            "_bucket{entry=\"x\",le=\"+Inf\"} 2",
+            "_sum{entry=\"x\"} 100003",
+        ] {
+            assert!(duration.contains(&format!("crondy_job_duration_seconds{}", expected)));
+        }
+    }
+
+    #[test]
+    fn counts_reloads() {
+        let metrics = Metrics::default();
+        assert!(!metrics.take_changed());
+        metrics.reloaded(true);
+        metrics.reloaded(false);
+        metrics.reloaded(false);
+        assert!(metrics.take_changed());
+        assert!(!metrics.take_changed());
+        assert_eq!(
+            lines(&metrics, "crondy_crontab_reloads_total"),
+            vec![
+                "crondy_crontab_reloads_total{result=\"success\"} 1",
+                "crondy_crontab_reloads_total{result=\"failure\"} 2"
+            ]
+        );
+    }
+
+    #[test]
+    fn writes_textfile() {
+        let path = temporary("metrics");
+        let metrics = Metrics::default();
+        metrics.reloaded(true);
+        metrics.write_textfile(&path).unwrap();
+        let written = fs::read_to_string(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(written, metrics.render());
+    }
+
+    #[test]
+    fn serves_http_on_loopback_only() {
+        let metrics = Metrics::default();
+        assert!(metrics.serve("0.0.0.0:0").is_err());
+        let address = metrics.serve("127.0.0.1:0").unwrap();
+        let get = |path: &str| {
+            let mut stream = TcpStream::connect(address).unwrap();
+            write!(stream, "GET {} HTTP/1.1\r\nHost: localhost\r\n\r\n", path).unwrap();
+            let mut response = String::new();
+            stream.read_to_string(&mut response).unwrap();
+            response
+        };
+        let response = get("/metrics");
+        assert!(response.starts_with("HTTP/1.0 200 OK\r\n"));
+        assert!(response.ends_with(&metrics.render()));
+        assert!(get("/").starts_with("HTTP/1.0 404"));
+    }
+}
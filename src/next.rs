@@ -0,0 +1,239 @@
+use crondy::clock::{self, Minute, Moment};
+use crondy::crontab::Crontab;
+use crondy::options;
+use crondy::schedule::Schedule;
+use failure::{bail, format_err, Error};
+
+use crate::json;
+
+/// How many fire times `crondy next` shows when not told otherwise.
+pub const DEFAULT_COUNT: usize = 5;
+
+/*
+ * What `crondy next` was asked for:
+ *
+ *     crondy next [--count N] [--within DURATION | --until TIME]
+ *                 [--from TIME] [--timezone TZ] [--json] [CRONTAB]
+ *
+ * Without --count or a window it shows DEFAULT_COUNT fire times per entry;
+ * with a window it shows all of them in it, up to --count if given as well.
+ * CRONTAB is a file, or - for stdin; without it, $CRONTAB or /etc/crontab.
+ */
+#[derive(Debug, Default, PartialEq)]
+pub struct Query {
+    pub count: Option<usize>,
+    pub within: Option<Minute>,
+    pub until: Option<Minute>,
+    pub from: Option<Minute>,
+    pub timezone: Option<String>,
+    pub json: bool,
+    pub crontab: Option<String>,
+}
+
+impl Query {
+    pub fn parse(args: &[String]) -> Result<Self, Error> {
+        let mut query = Query::default();
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            if !arg.starts_with("--") {
+                if query.crontab.is_some() {
+                    bail!("only one crontab at a time, got {:?} as well", arg);
+                }
+                query.crontab = Some(arg.clone());
+                continue;
+            }
+            let (name, inline) = match arg.find('=') {
+                Some(at) => (&arg[..at], Some(arg[at + 1..].to_owned())),
+                None => (arg.as_str(), None),
+            };
+            if name == "--json" {
+                query.json = true;
+                continue;
+            }
+            let value = match inline.or_else(|| args.next().cloned()) {
+                Some(value) => value,
+                None => bail!("{} needs a value", name),
+            };
+            let time = |value: &str| {
+                clock::parse_seconds(value).map(|seconds| seconds.div_euclid(60)).ok_or_else(|| {
+                    format_err!("{}: expected YYYY-MM-DD[THH:MM], got {:?}", name, value)
+                })
+            };
+            match name {
+                "--count" => match value.parse() {
+                    Ok(0) | Err(_) => bail!("--count must be a positive number, got {:?}", value),
+                    Ok(count) => query.count = Some(count),
+                },
+                "--within" => {
+                    let window = options::parse_duration("--within", &value)?;
+                    query.within = Some((window.as_secs() / 60) as Minute);
+                }
+                "--until" => query.until = Some(time(&value)?),
+                "--from" => query.from = Some(time(&value)?),
+                "--timezone" | "--tz" => query.timezone = Some(value),
+                _ => bail!("unknown option {:?}", name),
+            }
+        }
+        if query.within.is_some() && query.until.is_some() {
+            bail!("--within and --until don't go together");
+        }
+        Ok(query)
+    }
+
+    /// The last minute to look at, if there is a window.
+    fn last(&self, from: Minute) -> Option<Minute> {
+        self.until
+            .map(|until| until - 1)
+            .or_else(|| self.within.map(|within| from + within))
+    }
+
+    fn count(&self) -> Option<usize> {
+        match self.count {
+            None if self.within.is_none() && self.until.is_none() => Some(DEFAULT_COUNT),
+            count => count,
+        }
+    }
+}
+
+/// When `schedule` fires after `from`, as far as `query` wants to know.
+pub fn fire_times(schedule: &Schedule, from: Minute, query: &Query) -> Vec<Minute> {
+    let last = query.last(from);
+    let times = schedule
+        .upcoming(from)
+        .take_while(|&next| last.is_none_or(|last| next <= last));
+    match query.count() {
+        Some(count) => times.take(count).collect(),
+        None => times.collect(),
+    }
+}
+
+/// One block per entry: the entry, then what its schedule means and its
+/// fire times indented below.
+pub fn plain(crontab: &Crontab, from: Minute, query: &Query) -> String {
+    let mut out = String::new();
+    for entry in crontab.entries() {
+        out.push_str(&format!("{}: {}\n", entry.location(), entry.source()));
+        out.push_str(&format!("    {}\n", entry.schedule().describe()));
+        if let Schedule::Reboot = entry.schedule() {
+            continue;
+        }
+        let times = fire_times(entry.schedule(), from, query);
+        if times.is_empty() {
+            out.push_str("    not in that time\n");
+        }
+        for time in times {
+            out.push_str(&format!("    {}\n", Moment::at(time)));
+        }
+    }
+    out
+}
+
+/// A JSON array with an object per entry.
+pub fn json(crontab: &Crontab, from: Minute, query: &Query) -> String {
+    let entries: Vec<String> = crontab
+        .entries()
+        .iter()
+        .map(|entry| {
+            let reboot = *entry.schedule() == Schedule::Reboot;
+            let times: Vec<String> = fire_times(entry.schedule(), from, query)
+                .into_iter()
+                .map(|time| json::string(&Moment::at(time).to_string()))
+                .collect();
+            format!(
+                "{{\"line\":{},\"entry\":{},\"command\":{},\"description\":{},\"reboot\":{},\
+                 \"next\":[{}]}}",
+                entry.line(),
+                json::string(entry.source()),
+                json::string(entry.command()),
+                json::string(&entry.schedule().describe()),
+                reboot,
+                times.join(",")
+            )
+        })
+        .collect();
+    format!("[{}]\n", entries.join(","))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HOUR: Minute = 60;
+    const DAY: Minute = 24 * HOUR;
+
+    fn query(args: &[&str]) -> Query {
+        let args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+        Query::parse(&args).unwrap()
+    }
+
+    fn times(schedule: &str, args: &[&str]) -> Vec<Minute> {
+        let tab = Crontab::parse(&format!("{} true", schedule)).unwrap();
+        fire_times(tab.entries()[0].schedule(), 0, &query(args))
+    }
+
+    #[test]
+    fn parses_queries() {
+        assert_eq!(
+            query(&["--count", "3", "--tz=UTC", "--json", "/etc/crontab"]),
+            Query {
+                count: Some(3),
+                timezone: Some("UTC".to_owned()),
+                json: true,
+                crontab: Some("/etc/crontab".to_owned()),
+                ..Query::default()
+            }
+        );
+        assert_eq!(query(&["--within", "2d"]).within, Some(2 * DAY));
+        assert_eq!(query(&["--until", "1970-01-02"]).until, Some(DAY));
+    }
+
+    #[test]
+    fn rejects_bad_queries() {
+        let parse = |args: &[&str]| {
+            Query::parse(&args.iter().map(|arg| arg.to_string()).collect::<Vec<_>>())
+        };
+        assert!(parse(&["--count", "0"]).is_err());
+        assert!(parse(&["--within", "a while"]).is_err());
+        assert!(parse(&["--within", "1d", "--until", "1970-01-02"]).is_err());
+        assert!(parse(&["--from"]).is_err());
+        assert!(parse(&["a", "b"]).is_err());
+    }
+
+    #[test]
+    fn shows_a_few_by_default() {
+        assert_eq!(times("@hourly", &[]), vec![HOUR, 2 * HOUR, 3 * HOUR, 4 * HOUR, 5 * HOUR]);
+        assert_eq!(times("@daily", &["--count", "2"]), vec![DAY, 2 * DAY]);
+    }
+
+    #[test]
+    fn shows_everything_in_a_window() {
+        assert_eq!(times("0 */6 * * *", &["--within", "1d"]).len(), 4);
+        assert_eq!(times("0 */6 * * *", &["--until", "1970-01-02"]).len(), 3);
+        assert_eq!(times("0 */6 * * *", &["--within", "1d", "--count", "2"]).len(), 2);
+        assert!(times("@yearly", &["--within", "1d"]).is_empty());
+    }
+
+    #[test]
+    fn formats_plain_text() {
+        let tab = Crontab::parse("@reboot start\n0 12 * * * lunch").unwrap();
+        assert_eq!(
+            plain(&tab, 0, &query(&["--count", "2"])),
+            "line 1: @reboot start\n    when crondy starts\n\
+             line 2: 0 12 * * * lunch\n    at 12:00 every day\n    \
+             1970-01-01T12:00\n    1970-01-02T12:00\n"
+        );
+    }
+
+    #[test]
+    fn formats_json() {
+        let tab = Crontab::parse("@reboot start\n0 12 * * * say \"hi\"").unwrap();
+        assert_eq!(
+            json(&tab, 0, &query(&["--count", "1"])),
+            "[{\"line\":1,\"entry\":\"@reboot start\",\"command\":\"start\",\
+             \"description\":\"when crondy starts\",\"reboot\":true,\"next\":[]},\
+             {\"line\":2,\"entry\":\"0 12 * * * say \\\"hi\\\"\",\"command\":\"say \\\"hi\\\"\",\
+             \"description\":\"at 12:00 every day\",\"reboot\":false,\
+             \"next\":[\"1970-01-01T12:00\"]}]\n"
+        );
+    }
+}
@@ -0,0 +1,175 @@
+use crate::schedule::{
+    Field, Schedule, When, FIRST_DAY_OF_MONTH, FIRST_DAY_OF_WEEK, FIRST_HOUR, FIRST_MINUTE,
+    FIRST_MONTH, LAST_DAY_OF_MONTH, LAST_HOUR, LAST_MINUTE, LAST_MONTH,
+};
+
+/// Saturday: 7 is Sunday as well, but only 0-6 take part in stepping.
+const LAST_WEEKDAY: usize = 6;
+
+impl Schedule {
+    /// The plainest way to write the schedule, which is the same for every
+    /// way of writing the same times: `0,15,30,45 * * * *` becomes
+    /// `*/15 * * * *`. Only meaningful for a valid schedule.
+    pub fn normalize(&self) -> Schedule {
+        match self {
+            Schedule::Reboot => Schedule::Reboot,
+            Schedule::When(when) => Schedule::When(normalize(when)),
+        }
+    }
+
+    /// Whether the two schedules pick the same times, however written.
+    pub fn equivalent(&self, other: &Schedule) -> bool {
+        self.normalize() == other.normalize()
+    }
+}
+
+/*
+ * Each field comes down to the values it picks, written out again the
+ * plainest way: a star for all of them, a stepped star if that is what they
+ * are, otherwise ranges for runs and values for the rest.
+ *
+ * The day fields need more care, since whether either is a star decides if
+ * a day has to match both or only one of them. Matching either when one
+ * picks every day is every day, and otherwise the fields are written so
+ * that the same rule still holds. Days that don't exist, like the 30th of
+ * February, aren't looked into: `0 0 30 2 *` is only equivalent to
+ * schedules that pick that same date, not to every schedule that never runs.
+ */
+fn normalize(when: &When) -> When {
+    let mut days_of_month = values(&when.day_of_month, FIRST_DAY_OF_MONTH, LAST_DAY_OF_MONTH);
+    let mut days_of_week = values(&when.day_of_week, FIRST_DAY_OF_WEEK, LAST_WEEKDAY);
+    if when.day_of_week.contains(7, FIRST_DAY_OF_WEEK, LAST_WEEKDAY)
+        && days_of_week.first() != Some(&0)
+    {
+        days_of_week.insert(0, 0);
+    }
+    let mut both = when.day_of_month.is_star() || when.day_of_week.is_star();
+    let every_day_of_month = days_of_month.len() == LAST_DAY_OF_MONTH;
+    if !both && (every_day_of_month || days_of_week.len() == LAST_WEEKDAY + 1) {
+        days_of_month = (FIRST_DAY_OF_MONTH..=LAST_DAY_OF_MONTH).collect();
+        days_of_week = (FIRST_DAY_OF_WEEK..=LAST_WEEKDAY).collect();
+        both = true;
+    }
+
+    let mut day_of_month = field(&days_of_month, FIRST_DAY_OF_MONTH, LAST_DAY_OF_MONTH, both)
+        .unwrap_or_else(|| when.day_of_month.clone());
+    let mut day_of_week = field(&days_of_week, FIRST_DAY_OF_WEEK, LAST_WEEKDAY, both)
+        .unwrap_or_else(|| when.day_of_week.clone());
+    // a star stepped past everything but the first day, like `*/7`
+    if both && !day_of_month.is_star() && !day_of_week.is_star() {
+        if days_of_week == [FIRST_DAY_OF_WEEK] {
+            day_of_week = Field::Star(Some(LAST_WEEKDAY + 1));
+        } else {
+            day_of_month = Field::Star(Some(LAST_DAY_OF_MONTH));
+        }
+    }
+
+    let plain = |original: &Field, first, last| {
+        field(&values(original, first, last), first, last, true)
+            .unwrap_or_else(|| original.clone())
+    };
+    When {
+        minute: plain(&when.minute, FIRST_MINUTE, LAST_MINUTE),
+        hour: plain(&when.hour, FIRST_HOUR, LAST_HOUR),
+        day_of_month,
+        month: plain(&when.month, FIRST_MONTH, LAST_MONTH),
+        day_of_week,
+    }
+}
+
+fn values(field: &Field, first: usize, last: usize) -> Vec<usize> {
+    (first..=last).filter(|&value| field.contains(value, first, last)).collect()
+}
+
+/// The plainest field for the sorted `values` out of `first` to `last`,
+/// stepping over a star only if `stars` allows it. None if there are none.
+fn field(values: &[usize], first: usize, last: usize, stars: bool) -> Option<Field> {
+    if values.len() == last - first + 1 {
+        return Some(Field::Star(None));
+    }
+    if stars && values.len() > 1 && values[0] == first {
+        let step = values[1] - first;
+        if (first..=last).step_by(step).eq(values.iter().cloned()) {
+            return Some(Field::Star(Some(step)));
+        }
+    }
+    let mut items = Vec::new();
+    let mut rest = values;
+    while let Some(&start) = rest.first() {
+        let run = rest.iter().zip(start..).take_while(|&(value, next)| *value == next).count();
+        items.push(match run {
+            1 => Field::Value(start),
+            _ => Field::Range(start, start + run - 1, None),
+        });
+        rest = &rest[run..];
+    }
+    match items.len() {
+        0 => None,
+        1 => items.pop(),
+        _ => Some(Field::List(items)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::Schedule;
+    use quickcheck::quickcheck;
+
+    fn schedule(schedule: &str) -> Schedule {
+        let (_, schedule) = Schedule::parse(&format!("{} ", schedule)).unwrap();
+        schedule
+    }
+
+    fn normalize(input: &str) -> String {
+        schedule(input).normalize().to_string()
+    }
+
+    fn equivalent(a: &str, b: &str) -> bool {
+        schedule(a).equivalent(&schedule(b))
+    }
+
+    #[test]
+    fn normalizes_fields() {
+        assert_eq!(normalize("0,15,30,45 * * * *"), "*/15 * * * *");
+        assert_eq!(normalize("0-59 */1 1-31 1-12 0-7"), "* * * * *");
+        assert_eq!(normalize("7,5,6,9 22-2 * * *"), "5-7,9 0-2,22-23 * * *");
+        assert_eq!(normalize("0 9-17/4 * jan,jul *"), "0 9,13,17 * */6 *");
+        assert_eq!(normalize("30 12 * * 7"), "30 12 * * 0");
+        assert_eq!(normalize("@weekly"), "0 0 * * 0");
+        assert_eq!(normalize("@reboot"), "@reboot");
+    }
+
+    #[test]
+    fn keeps_the_day_rule() {
+        assert_eq!(normalize("0 0 */2 * 1"), "0 0 */2 * 1");
+        assert_eq!(normalize("0 0 1 * */7"), "0 0 1 * */7");
+        assert_eq!(normalize("0 0 */31 * 1,3"), "0 0 */31 * 1,3");
+        assert_eq!(normalize("0 0 1-7 * 1-5"), "0 0 1-7 * 1-5");
+        assert_eq!(normalize("0 0 1-31 * 1"), "0 0 * * *");
+        assert_eq!(normalize("0 0 13 * 0-6"), "0 0 * * *");
+    }
+
+    #[test]
+    fn compares_what_schedules_mean() {
+        assert!(equivalent("0,15,30,45 * * * *", "*/15 * * * *"));
+        assert!(equivalent("@daily", "0 0 1-31 * *"));
+        assert!(equivalent("0 0 * * sun", "0 0 * * 7"));
+        assert!(equivalent("0 22-2 * * *", "0 0,1,2,22,23 * * *"));
+        assert!(equivalent("@reboot", "@reboot"));
+        assert!(!equivalent("@reboot", "@daily"));
+        assert!(!equivalent("0 0 */2 * 1", "0 0 1-31/2 * 1"));
+        assert!(!equivalent("0 0 1 * 1", "0 0 1 * */7"));
+    }
+
+    quickcheck! {
+        fn normalizes_to_the_same_times(schedule: Schedule) -> bool {
+            if schedule.validate_wrapping().is_err() {
+                return true;
+            }
+            let normal = schedule.normalize();
+            normal.validate().is_ok()
+                && normal.normalize() == normal
+                && normal.upcoming(0).take(10).eq(schedule.upcoming(0).take(10))
+        }
+    }
+}
@@ -0,0 +1,335 @@
+use std::fmt;
+use std::time::Duration;
+
+use failure::{bail, Error};
+
+/*
+ * Settings that tune how crondy treats an entry rather than what the entry
+ * runs. They are written in the crontab like environment variables with a
+ * CRONDY_ prefix and, like environment variables, apply to every entry below
+ * them. They are not passed on to the job.
+ */
+#[derive(Debug, Clone, PartialEq)]
+pub struct Options {
+    /// Read ranges whose start comes after their end as wrapping around the
+    /// end of the field (CRONDY_WRAP_RANGES).
+    pub wrap_ranges: bool,
+    /// What to do when the entry comes due while it is still running
+    /// (CRONDY_OVERLAP).
+    pub overlap: Overlap,
+    /// How long a run may take before it gets terminated (CRONDY_TIMEOUT).
+    pub timeout: Option<Duration>,
+    /// How long a terminated run gets to exit before it gets killed
+    /// (CRONDY_KILL_GRACE).
+    pub kill_grace: Duration,
+    /// Runs with a higher priority leave the run queue first
+    /// (CRONDY_PRIORITY).
+    pub priority: i32,
+    /// How many more times to try a run that fails (CRONDY_RETRIES).
+    pub retries: u32,
+    /// How long to wait before the first retry (CRONDY_RETRY_DELAY).
+    pub retry_delay: Duration,
+    /// What to multiply the wait by for each further retry
+    /// (CRONDY_RETRY_BACKOFF).
+    pub retry_backoff: f64,
+    /// How many runs missed while crondy was not running to make up for when
+    /// it starts (CRONDY_CATCHUP).
+    pub catch_up: u32,
+    /// How far back to look for missed runs (CRONDY_CATCHUP_WINDOW).
+    pub catch_up_window: Duration,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            wrap_ranges: false,
+            overlap: Overlap::default(),
+            timeout: None,
+            kill_grace: Duration::from_secs(10),
+            priority: 0,
+            retries: 0,
+            retry_delay: Duration::from_secs(60),
+            retry_backoff: 2.0,
+            catch_up: 0,
+            catch_up_window: Duration::from_secs(7 * 24 * 60 * 60),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum Overlap {
+    /// Start another run alongside the ones still going (allow).
+    #[default]
+    Allow,
+    /// Don't run this time (skip).
+    Skip,
+    /// Run once the running ones are done, remembering at most one such run
+    /// (queue).
+    Queue,
+    /// Terminate the running ones and start over (kill).
+    Kill,
+}
+
+impl fmt::Display for Overlap {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Overlap::Allow => "allow",
+            Overlap::Skip => "skip",
+            Overlap::Queue => "queue",
+            Overlap::Kill => "kill",
+        })
+    }
+}
+
+impl Options {
+    /// Whether `name` is one of the settings rather than a variable for jobs.
+    pub fn is_setting(name: &str) -> bool {
+        Options::default().settings().iter().any(|(setting, _)| *setting == name)
+    }
+
+    pub fn set(&mut self, name: &str, value: &str) -> Result<(), Error> {
+        match name {
+            "CRONDY_WRAP_RANGES" => self.wrap_ranges = parse_flag(name, value)?,
+            "CRONDY_OVERLAP" => {
+                self.overlap = match value.trim() {
+                    "allow" => Overlap::Allow,
+                    "skip" => Overlap::Skip,
+                    "queue" => Overlap::Queue,
+                    "kill" => Overlap::Kill,
+                    _ => bail!(
+                        "{} must be one of allow, skip, queue or kill (got {})",
+                        name,
+                        value
+                    ),
+                }
+            }
+            "CRONDY_TIMEOUT" => {
+                self.timeout = match value.trim() {
+                    "none" => None,
+                    _ => Some(parse_duration(name, value)?).filter(|d| *d > Duration::new(0, 0)),
+                }
+            }
+            "CRONDY_KILL_GRACE" => self.kill_grace = parse_duration(name, value)?,
+            "CRONDY_PRIORITY" => {
+                self.priority = match value.trim().parse() {
+                    Ok(priority) => priority,
+                    Err(_) => bail!("{} must be a whole number (got {})", name, value),
+                }
+            }
+            "CRONDY_RETRIES" => {
+                self.retries = match value.trim().parse() {
+                    Ok(retries) => retries,
+                    Err(_) => bail!("{} must be a whole number (got {})", name, value),
+                }
+            }
+            "CRONDY_RETRY_DELAY" => self.retry_delay = parse_duration(name, value)?,
+            "CRONDY_RETRY_BACKOFF" => {
+                self.retry_backoff = match value.trim().parse::<f64>() {
+                    Ok(backoff) if backoff >= 1.0 && backoff.is_finite() => backoff,
+                    _ => bail!("{} must be a number no less than 1 (got {})", name, value),
+                }
+            }
+            "CRONDY_CATCHUP" => {
+                self.catch_up = match value.trim() {
+                    "no" => 0,
+                    "yes" => 1,
+                    number => match number.parse() {
+                        Ok(catch_up) => catch_up,
+                        Err(_) => bail!("{} must be yes, no or a whole number (got {})", name, value),
+                    },
+                }
+            }
+            "CRONDY_CATCHUP_WINDOW" => self.catch_up_window = parse_duration(name, value)?,
+            _ => bail!("unknown setting {}", name),
+        }
+        Ok(())
+    }
+
+    /// Every setting, as the name and value that `set` takes to get it.
+    pub fn settings(&self) -> Vec<(&'static str, String)> {
+        let duration = |duration: Duration| format!("{}s", duration.as_secs());
+        vec![
+            ("CRONDY_WRAP_RANGES", if self.wrap_ranges { "yes" } else { "no" }.to_owned()),
+            ("CRONDY_OVERLAP", self.overlap.to_string()),
+            ("CRONDY_TIMEOUT", self.timeout.map_or("none".to_owned(), duration)),
+            ("CRONDY_KILL_GRACE", duration(self.kill_grace)),
+            ("CRONDY_PRIORITY", self.priority.to_string()),
+            ("CRONDY_RETRIES", self.retries.to_string()),
+            ("CRONDY_RETRY_DELAY", duration(self.retry_delay)),
+            ("CRONDY_RETRY_BACKOFF", self.retry_backoff.to_string()),
+            ("CRONDY_CATCHUP", self.catch_up.to_string()),
+            ("CRONDY_CATCHUP_WINDOW", duration(self.catch_up_window)),
+        ]
+    }
+}
+
+/*
+ * Durations are a whole number followed by s, m, h or d; a bare number is
+ * seconds.
+ */
+pub fn parse_duration(name: &str, value: &str) -> Result<Duration, Error> {
+    let value = value.trim();
+    let (number, unit) = match value.find(|c: char| !c.is_ascii_digit()) {
+        Some(at) => value.split_at(at),
+        None => (value, "s"),
+    };
+    let multiplier = match unit {
+        "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        _ => bail!("{} must be a duration like 90s, 5m or 2h (got {})", name, value),
+    };
+    match number.parse::<u64>().ok().and_then(|n| n.checked_mul(multiplier)) {
+        Some(seconds) => Ok(Duration::from_secs(seconds)),
+        None => bail!("{} must be a duration like 90s, 5m or 2h (got {})", name, value),
+    }
+}
+
+pub fn parse_flag(name: &str, value: &str) -> Result<bool, Error> {
+    match value.trim().to_ascii_lowercase().as_str() {
+        "1" | "yes" | "true" | "on" => Ok(true),
+        "0" | "no" | "false" | "off" => Ok(false),
+        _ => bail!("{} must be yes or no (got {})", name, value),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn defaults_to_strict_ranges() {
+        assert!(!Options::default().wrap_ranges)
+    }
+
+    #[test]
+    fn sets_wrap_ranges() {
+        let mut options = Options::default();
+        options.set("CRONDY_WRAP_RANGES", "Yes").unwrap();
+        assert!(options.wrap_ranges);
+        options.set("CRONDY_WRAP_RANGES", "off").unwrap();
+        assert!(!options.wrap_ranges);
+    }
+
+    #[test]
+    fn sets_overlap() {
+        let mut options = Options::default();
+        assert_eq!(options.overlap, Overlap::Allow);
+        options.set("CRONDY_OVERLAP", "queue").unwrap();
+        assert_eq!(options.overlap, Overlap::Queue);
+        assert!(options.set("CRONDY_OVERLAP", "sometimes").is_err());
+    }
+
+    #[test]
+    fn sets_timeouts() {
+        let mut options = Options::default();
+        assert_eq!(options.timeout, None);
+        assert_eq!(options.kill_grace, Duration::from_secs(10));
+        options.set("CRONDY_TIMEOUT", "2h").unwrap();
+        assert_eq!(options.timeout, Some(Duration::from_secs(7200)));
+        options.set("CRONDY_KILL_GRACE", "30").unwrap();
+        assert_eq!(options.kill_grace, Duration::from_secs(30));
+        options.set("CRONDY_TIMEOUT", "none").unwrap();
+        assert_eq!(options.timeout, None);
+        options.set("CRONDY_TIMEOUT", "0").unwrap();
+        assert_eq!(options.timeout, None);
+    }
+
+    #[test]
+    fn rejects_bad_durations() {
+        let mut options = Options::default();
+        assert!(options.set("CRONDY_TIMEOUT", "5 minutes").is_err());
+        assert!(options.set("CRONDY_TIMEOUT", "m").is_err());
+        assert!(options.set("CRONDY_KILL_GRACE", "-1").is_err());
+        assert!(options.set("CRONDY_TIMEOUT", "999999999999999999d").is_err());
+        assert!(options.set("CRONDY_RETRY_DELAY", "99999999999999999999").is_err());
+    }
+
+    #[test]
+    fn parses_the_longest_durations() {
+        let most = u64::MAX / (24 * 60 * 60);
+        assert_eq!(
+            parse_duration("--within", &format!("{}d", most)).unwrap(),
+            Duration::from_secs(most * 24 * 60 * 60)
+        );
+        assert!(parse_duration("--within", &format!("{}d", most + 1)).is_err());
+        assert_eq!(
+            parse_duration("--within", &format!("{}", u64::MAX)).unwrap(),
+            Duration::from_secs(u64::MAX)
+        );
+    }
+
+    #[test]
+    fn sets_priority() {
+        let mut options = Options::default();
+        assert_eq!(options.priority, 0);
+        options.set("CRONDY_PRIORITY", "-3").unwrap();
+        assert_eq!(options.priority, -3);
+        assert!(options.set("CRONDY_PRIORITY", "high").is_err());
+    }
+
+    #[test]
+    fn sets_retries() {
+        let mut options = Options::default();
+        assert_eq!(options.retries, 0);
+        options.set("CRONDY_RETRIES", "3").unwrap();
+        options.set("CRONDY_RETRY_DELAY", "30s").unwrap();
+        options.set("CRONDY_RETRY_BACKOFF", "1.5").unwrap();
+        assert_eq!(options.retries, 3);
+        assert_eq!(options.retry_delay, Duration::from_secs(30));
+        assert_eq!(options.retry_backoff, 1.5);
+        assert!(options.set("CRONDY_RETRIES", "-1").is_err());
+        assert!(options.set("CRONDY_RETRY_BACKOFF", "0.5").is_err());
+    }
+
+    #[test]
+    fn sets_catch_up() {
+        let mut options = Options::default();
+        assert_eq!(options.catch_up, 0);
+        options.set("CRONDY_CATCHUP", "yes").unwrap();
+        assert_eq!(options.catch_up, 1);
+        options.set("CRONDY_CATCHUP", "3").unwrap();
+        assert_eq!(options.catch_up, 3);
+        options.set("CRONDY_CATCHUP_WINDOW", "2d").unwrap();
+        assert_eq!(options.catch_up_window, Duration::from_secs(2 * 24 * 60 * 60));
+        assert!(options.set("CRONDY_CATCHUP", "always").is_err());
+    }
+
+    #[test]
+    fn rejects_bad_flags() {
+        assert!(Options::default().set("CRONDY_WRAP_RANGES", "maybe").is_err())
+    }
+
+    #[test]
+    fn rejects_unknown_settings() {
+        assert!(Options::default().set("CRONDY_NOPE", "1").is_err())
+    }
+
+    #[test]
+    fn recognizes_settings() {
+        assert!(Options::is_setting("CRONDY_WRAP_RANGES"));
+        assert!(!Options::is_setting("PATH"));
+        assert!(!Options::is_setting("CRONDY_LOG"));
+    }
+
+    #[test]
+    fn settings_give_the_same_options() {
+        let mut options = Options::default();
+        for (name, value) in &[
+            ("CRONDY_OVERLAP", "kill"),
+            ("CRONDY_TIMEOUT", "90m"),
+            ("CRONDY_PRIORITY", "-3"),
+            ("CRONDY_RETRY_BACKOFF", "1.5"),
+            ("CRONDY_CATCHUP", "yes"),
+        ] {
+            options.set(name, value).unwrap();
+        }
+        let mut again = Options::default();
+        for (name, value) in options.settings() {
+            again.set(name, &value).unwrap();
+        }
+        assert_eq!(again, options);
+        assert!(options.settings().contains(&("CRONDY_TIMEOUT", "5400s".to_owned())));
+    }
+}
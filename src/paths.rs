@@ -0,0 +1,92 @@
+use std::path::Path;
+
+/// Where root's pidfile lives unless CRONDY_PIDFILE says otherwise.
+pub const DEFAULT_PIDFILE: &str = "/run/crondy.pid";
+
+/// Where root's control socket lives unless CRONDY_SOCKET says otherwise.
+pub const DEFAULT_SOCKET: &str = "/run/crondy.sock";
+
+/// Where root's run history lives unless CRONDY_STATE_DIR says otherwise.
+pub const DEFAULT_STATE_DIR: &str = "/var/lib/crondy";
+
+/*
+ * Where crondy keeps its files unless told otherwise, shared with crondyctl
+ * so that it finds the socket on its own as well. Only root can write to
+ * /run and /var/lib, so anyone else gets their pidfile and socket in their
+ * runtime directory and their state in their state directory, as the XDG
+ * base directory spec has them, or none if they have no such directory.
+ */
+
+/// The pidfile for the user `euid`, with `env` looking up variables.
+pub fn pidfile(euid: libc::uid_t, env: &dyn Fn(&str) -> Option<String>) -> Option<String> {
+    match euid {
+        0 => Some(DEFAULT_PIDFILE.to_owned()),
+        _ => in_dir(env("XDG_RUNTIME_DIR"), "crondy.pid"),
+    }
+}
+
+/// The control socket for the user `euid`, like `pidfile`.
+pub fn socket(euid: libc::uid_t, env: &dyn Fn(&str) -> Option<String>) -> Option<String> {
+    match euid {
+        0 => Some(DEFAULT_SOCKET.to_owned()),
+        _ => in_dir(env("XDG_RUNTIME_DIR"), "crondy.sock"),
+    }
+}
+
+/// The state directory for the user `euid`, like `pidfile`.
+pub fn state_dir(euid: libc::uid_t, env: &dyn Fn(&str) -> Option<String>) -> Option<String> {
+    if euid == 0 {
+        return Some(DEFAULT_STATE_DIR.to_owned());
+    }
+    let home = env("HOME")
+        .filter(|home| !home.is_empty())
+        .map(|home| Path::new(&home).join(".local/state").to_string_lossy().into_owned());
+    in_dir(env("XDG_STATE_HOME").filter(|dir| !dir.is_empty()).or(home), "crondy")
+}
+
+fn in_dir(dir: Option<String>, name: &str) -> Option<String> {
+    dir.filter(|dir| !dir.is_empty())
+        .map(|dir| Path::new(&dir).join(name).to_string_lossy().into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn env(vars: &'static [(&str, &str)]) -> impl Fn(&str) -> Option<String> {
+        move |name| {
+            vars.iter()
+                .find(|(key, _)| *key == name)
+                .map(|(_, value)| value.to_string())
+        }
+    }
+
+    #[test]
+    fn keeps_roots_files_in_the_system_directories() {
+        let env = env(&[("XDG_RUNTIME_DIR", "/run/user/0"), ("HOME", "/root")]);
+        assert_eq!(pidfile(0, &env).as_deref(), Some(DEFAULT_PIDFILE));
+        assert_eq!(socket(0, &env).as_deref(), Some(DEFAULT_SOCKET));
+        assert_eq!(state_dir(0, &env).as_deref(), Some(DEFAULT_STATE_DIR));
+    }
+
+    #[test]
+    fn keeps_everyone_elses_files_in_their_own_directories() {
+        let env = env(&[
+            ("XDG_RUNTIME_DIR", "/run/user/1000"),
+            ("XDG_STATE_HOME", "/home/me/state"),
+            ("HOME", "/home/me"),
+        ]);
+        assert_eq!(pidfile(1000, &env).as_deref(), Some("/run/user/1000/crondy.pid"));
+        assert_eq!(socket(1000, &env).as_deref(), Some("/run/user/1000/crondy.sock"));
+        assert_eq!(state_dir(1000, &env).as_deref(), Some("/home/me/state/crondy"));
+    }
+
+    #[test]
+    fn falls_back_like_the_base_directory_spec() {
+        let home = env(&[("XDG_STATE_HOME", ""), ("HOME", "/home/me")]);
+        assert_eq!(state_dir(1000, &home).as_deref(), Some("/home/me/.local/state/crondy"));
+        assert_eq!(pidfile(1000, &home), None);
+        assert_eq!(socket(1000, &home), None);
+        assert_eq!(state_dir(1000, &|_| None), None);
+    }
+}
@@ -0,0 +1,140 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::os::unix::fs::{MetadataExt, OpenOptionsExt};
+use std::os::unix::io::AsRawFd;
+use std::path::{Path, PathBuf};
+use std::process;
+
+use failure::{bail, format_err, Error};
+use log::info;
+
+/*
+ * A file with our pid in it, locked with flock(2) for as long as we run so
+ * that a second crondy can tell it should not start. The lock goes away
+ * with the process however that ends, so a pidfile nobody holds a lock on
+ * was left behind by a crondy that is gone, and is simply taken over.
+ */
+#[derive(Debug)]
+pub struct Pidfile {
+    path: PathBuf,
+    file: File,
+}
+
+impl Pidfile {
+    pub fn acquire(path: &Path) -> Result<Self, Error> {
+        let mut file = loop {
+            let mut file = OpenOptions::new()
+                .read(true)
+                .write(true)
+                .create(true)
+                .truncate(false)
+                .mode(0o644)
+                .open(path)
+                .map_err(|error| {
+                    format_err!("could not open pidfile {}: {}", path.display(), error)
+                })?;
+            if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX | libc::LOCK_NB) } != 0 {
+                let error = io::Error::last_os_error();
+                if error.kind() == io::ErrorKind::WouldBlock {
+                    match read_pid(&mut file) {
+                        Some(pid) => bail!(
+                            "crondy is already running as pid {} (see {})",
+                            pid,
+                            path.display()
+                        ),
+                        None => bail!("crondy is already running (see {})", path.display()),
+                    }
+                }
+                bail!("could not lock pidfile {}: {}", path.display(), error);
+            }
+            /*
+             * The crondy before us removes the file on its way out, maybe
+             * after we opened it but before we got the lock. Then we hold a
+             * lock on a file nobody else will see, so start over.
+             */
+            let still_there = is_at(&file, path).map_err(|error| {
+                format_err!("could not check pidfile {}: {}", path.display(), error)
+            })?;
+            if still_there {
+                break file;
+            }
+        };
+        if let Some(pid) = read_pid(&mut file) {
+            info!("taking over pidfile {} left behind by pid {}", path.display(), pid);
+        }
+        file.set_len(0)
+            .and_then(|_| file.seek(SeekFrom::Start(0)))
+            .and_then(|_| writeln!(file, "{}", process::id()))
+            .map_err(|error| format_err!("could not write pidfile {}: {}", path.display(), error))?;
+        Ok(Pidfile {
+            path: path.to_owned(),
+            file,
+        })
+    }
+}
+
+impl Drop for Pidfile {
+    fn drop(&mut self) {
+        // still locked, so nobody else can have taken it over
+        let _ = fs::remove_file(&self.path);
+        unsafe {
+            libc::flock(self.file.as_raw_fd(), libc::LOCK_UN);
+        }
+    }
+}
+
+/// Whether `file` is the one found at `path`, which it isn't if that has
+/// been removed or replaced since it was opened.
+fn is_at(file: &File, path: &Path) -> io::Result<bool> {
+    let ours = file.metadata()?;
+    match fs::metadata(path) {
+        Ok(found) => Ok((ours.dev(), ours.ino()) == (found.dev(), found.ino())),
+        Err(ref error) if error.kind() == io::ErrorKind::NotFound => Ok(false),
+        Err(error) => Err(error),
+    }
+}
+
+fn read_pid(file: &mut File) -> Option<u32> {
+    let mut contents = String::new();
+    file.seek(SeekFrom::Start(0)).ok()?;
+    file.read_to_string(&mut contents).ok()?;
+    contents.trim().parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_files::temporary;
+
+    #[test]
+    fn holds_off_a_second_instance() {
+        let path = temporary("pidfile-second");
+        let pidfile = Pidfile::acquire(&path).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), format!("{}\n", process::id()));
+        let error = Pidfile::acquire(&path).unwrap_err().to_string();
+        assert!(error.contains(&format!("already running as pid {}", process::id())));
+        drop(pidfile);
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn takes_over_stale_pidfiles() {
+        let path = temporary("pidfile-stale");
+        fs::write(&path, "123456789\n").unwrap();
+        let pidfile = Pidfile::acquire(&path).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), format!("{}\n", process::id()));
+        drop(pidfile);
+    }
+
+    #[test]
+    fn notices_a_pidfile_removed_under_it() {
+        let path = temporary("pidfile-removed");
+        let file = File::create(&path).unwrap();
+        assert!(is_at(&file, &path).unwrap());
+        fs::remove_file(&path).unwrap();
+        assert!(!is_at(&file, &path).unwrap());
+        File::create(&path).unwrap();
+        assert!(!is_at(&file, &path).unwrap());
+        fs::remove_file(&path).unwrap();
+    }
+}
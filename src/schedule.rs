@@ -1,45 +1,126 @@
+use std::error;
+use std::fmt;
 use std::str::FromStr;
 
 use nom::{
-    alt, call, char, complete, digit1, error_position, map, map_opt, map_res, named, opt, preceded,
-    separated_list, separated_list_complete, separated_pair, space1, tag, tuple, tuple_parser,
+    alt, call, char, complete, digit1, do_parse, error_position, map, map_res, named, not, opt,
+    preceded, separated_nonempty_list, separated_nonempty_list_complete,
+    separated_pair, space1, tag, terminated, tuple, tuple_parser,
 };
 
-use failure::{bail, format_err, Error};
+use crate::clock::{Minute, Moment};
 
-const FIRST_MINUTE: usize = 0;
-const LAST_MINUTE: usize = 59;
+pub(crate) const FIRST_MINUTE: usize = 0;
+pub(crate) const LAST_MINUTE: usize = 59;
 
-const FIRST_HOUR: usize = 0;
-const LAST_HOUR: usize = 23;
+pub(crate) const FIRST_HOUR: usize = 0;
+pub(crate) const LAST_HOUR: usize = 23;
 
-const FIRST_DAY_OF_MONTH: usize = 1;
-const LAST_DAY_OF_MONTH: usize = 31;
+pub(crate) const FIRST_DAY_OF_MONTH: usize = 1;
+pub(crate) const LAST_DAY_OF_MONTH: usize = 31;
 
-const FIRST_MONTH: usize = 1;
-const LAST_MONTH: usize = 12;
+pub(crate) const FIRST_MONTH: usize = 1;
+pub(crate) const LAST_MONTH: usize = 12;
 
 /* note on DAY_OF_WEEK: 0 and 7 are both Sunday, for compatibility reasons. */
-const FIRST_DAY_OF_WEEK: usize = 0;
-const LAST_DAY_OF_WEEK: usize = 7;
+pub(crate) const FIRST_DAY_OF_WEEK: usize = 0;
+pub(crate) const LAST_DAY_OF_WEEK: usize = 7;
 
-#[derive(Debug, PartialEq)]
+/// Why a schedule was refused, such as `minute value too high (got 60 but
+/// expected no more than 59)`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScheduleError(String);
+
+impl fmt::Display for ScheduleError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl error::Error for ScheduleError {}
+
+macro_rules! invalid {
+    ($($arg:tt)*) => {
+        return Err(ScheduleError(format!($($arg)*)))
+    };
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub enum Schedule {
     Reboot,
     When(When),
 }
 
 impl Schedule {
-    pub fn parse(input: &str) -> nom::IResult<&str, Self> {
+    pub(crate) fn parse(input: &str) -> nom::IResult<&str, Self> {
         parse_schedule(input)
     }
 
-    pub fn validate(&self) -> Result<(), Error> {
+    pub fn validate(&self) -> Result<(), ScheduleError> {
         match &self {
             Schedule::Reboot => Ok(()),
             Schedule::When(when) => when.validate(),
         }
     }
+
+    /// Like `validate`, but accepts ranges whose start comes after their end,
+    /// which are read as wrapping past the top of the field (`22-2` is hours
+    /// 22, 23, 0, 1 and 2).
+    pub fn validate_wrapping(&self) -> Result<(), ScheduleError> {
+        match &self {
+            Schedule::Reboot => Ok(()),
+            Schedule::When(when) => when.validate_wrapping(),
+        }
+    }
+
+    /// The first minute after `minute` that this schedule fires at, if any.
+    pub fn next_after(&self, minute: Minute) -> Option<Minute> {
+        match &self {
+            Schedule::Reboot => None,
+            Schedule::When(when) => when.next_after(minute),
+        }
+    }
+
+    /// Every minute after `minute` that this schedule fires at, in order.
+    pub fn upcoming(&self, minute: Minute) -> impl Iterator<Item = Minute> + '_ {
+        std::iter::successors(self.next_after(minute), move |&after| self.next_after(after))
+    }
+}
+
+/*
+ * A whole schedule on its own, such as `0 9 * * 1-5` or `@daily`, which has
+ * to be valid as well as make sense. The parsers expect something to follow
+ * a schedule, as the command does in a crontab, so they get a space.
+ */
+impl FromStr for Schedule {
+    type Err = ScheduleError;
+
+    fn from_str(input: &str) -> Result<Self, ScheduleError> {
+        let input = format!("{} ", input.trim());
+        match parse_schedule(&input) {
+            Ok((" ", schedule)) => {
+                schedule.validate()?;
+                Ok(schedule)
+            }
+            Ok((remaining, _)) => invalid!("could not make sense of {:?}", remaining.trim()),
+            Err(_) => invalid!("expected five fields or one of @reboot, @yearly, @annually, \
+                                @monthly, @weekly, @daily, @midnight or @hourly"),
+        }
+    }
+}
+
+/*
+ * Schedules print in canonical form, which parses back to the same schedule:
+ * always five fields of numbers, so nicknames like @daily are spelled out
+ * and month and day names become numbers. Only @reboot stays as it is.
+ */
+impl fmt::Display for Schedule {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Schedule::Reboot => f.write_str("@reboot"),
+            Schedule::When(when) => when.fmt(f),
+        }
+    }
 }
 
 named!(parse_schedule<&str, Schedule>, alt!(
@@ -85,10 +166,10 @@ named!(parse_schedule<&str, Schedule>, alt!(
              month: Field::Star(None),
              day_of_week: Field::Star(None),
          })) |
-    map!(parse_when, |when| Schedule::When(when))
+    map!(parse_when, Schedule::When)
 ));
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct When {
     pub minute: Field,
     pub hour: Field,
@@ -98,77 +179,198 @@ pub struct When {
 }
 
 impl When {
-    pub fn validate(&self) -> Result<(), Error> {
-        self.minute
-            .validate(FIRST_MINUTE, LAST_MINUTE)
-            .map_err(|e| format_err!("minute {}", e))
+    pub fn validate(&self) -> Result<(), ScheduleError> {
+        self.check(false)
+    }
+
+    pub fn validate_wrapping(&self) -> Result<(), ScheduleError> {
+        self.check(true)
+    }
+
+    pub fn matches(&self, moment: &Moment) -> bool {
+        self.day_matches(moment)
+            && self.minute.contains(moment.minute, FIRST_MINUTE, LAST_MINUTE)
+            && self.hour.contains(moment.hour, FIRST_HOUR, LAST_HOUR)
+    }
+
+    /*
+     * As in Vixie cron, when either day field starts with a star the day has
+     * to match both of them, otherwise it only has to match one.
+     */
+    fn day_matches(&self, moment: &Moment) -> bool {
+        let day_of_month = self.day_of_month.contains(
+            moment.day_of_month,
+            FIRST_DAY_OF_MONTH,
+            LAST_DAY_OF_MONTH,
+        );
+        // 7 is Sunday too, but only 0-6 take part in wrapping and stepping
+        let day_of_week = self.day_of_week.contains(moment.day_of_week, FIRST_DAY_OF_WEEK, 6)
+            || (moment.day_of_week == 0 && self.day_of_week.contains(7, FIRST_DAY_OF_WEEK, 6));
+        let day = if self.day_of_month.is_star() || self.day_of_week.is_star() {
+            day_of_month && day_of_week
+        } else {
+            day_of_month || day_of_week
+        };
+        day && self.month.contains(moment.month, FIRST_MONTH, LAST_MONTH)
+    }
+
+    /*
+     * Walks forward a day at a time, only looking at the hours and minutes
+     * of days that match. Gives up after eight years, which is long enough
+     * for a 29th of February to come around even across a skipped leap year.
+     */
+    pub fn next_after(&self, minute: Minute) -> Option<Minute> {
+        const DAY: Minute = 24 * 60;
+        let first_day = (minute + 1).div_euclid(DAY);
+        for day in first_day..first_day + 8 * 366 {
+            if !self.day_matches(&Moment::at(day * DAY)) {
+                continue;
+            }
+            for candidate in (day * DAY).max(minute + 1)..(day + 1) * DAY {
+                if self.matches(&Moment::at(candidate)) {
+                    return Some(candidate);
+                }
+            }
+        }
+        None
+    }
+
+    /// Wildcard entries are those whose minute or hour starts with a star;
+    /// they get different treatment when the clock jumps.
+    pub fn is_wildcard(&self) -> bool {
+        self.minute.is_star() || self.hour.is_star()
+    }
+
+    fn check(&self, wrap: bool) -> Result<(), ScheduleError> {
+        let check = |field: &Field, lower_bound, upper_bound| {
+            if wrap {
+                field.validate_wrapping(lower_bound, upper_bound)
+            } else {
+                field.validate(lower_bound, upper_bound)
+            }
+        };
+        check(&self.minute, FIRST_MINUTE, LAST_MINUTE)
+            .map_err(|e| ScheduleError(format!("minute {}", e)))
             .and(
-                self.hour
-                    .validate(FIRST_HOUR, LAST_HOUR)
-                    .map_err(|e| format_err!("hour {}", e)),
+                check(&self.hour, FIRST_HOUR, LAST_HOUR)
+                    .map_err(|e| ScheduleError(format!("hour {}", e))),
             )
             .and(
-                self.day_of_month
-                    .validate(FIRST_DAY_OF_MONTH, LAST_DAY_OF_MONTH)
-                    .map_err(|e| format_err!("day of month {}", e)),
+                check(&self.day_of_month, FIRST_DAY_OF_MONTH, LAST_DAY_OF_MONTH)
+                    .map_err(|e| ScheduleError(format!("day of month {}", e))),
             )
             .and(
-                self.month
-                    .validate(FIRST_MONTH, LAST_MONTH)
-                    .map_err(|e| format_err!("month {}", e)),
+                check(&self.month, FIRST_MONTH, LAST_MONTH)
+                    .map_err(|e| ScheduleError(format!("month {}", e))),
             )
             .and(
-                self.day_of_week
-                    .validate(FIRST_DAY_OF_WEEK, LAST_DAY_OF_WEEK)
-                    .map_err(|e| format_err!("day of week {}", e)),
+                check(&self.day_of_week, FIRST_DAY_OF_WEEK, LAST_DAY_OF_WEEK)
+                    .map_err(|e| ScheduleError(format!("day of week {}", e))),
             )
     }
 }
 
-fn parse_when(input: &str) -> nom::IResult<&str, When> {
-    named!(inner<&str, Vec<Field>>, separated_list_complete!(space1, parse_field));
-    match inner(input) {
-        Ok((remaining, fields)) => {
-            if fields.len() == 5 {
-                Ok((
-                    remaining,
-                    When {
-                        minute: fields[0].clone(),
-                        hour: fields[1].clone(),
-                        day_of_month: fields[2].clone(),
-                        month: fields[3].clone(),
-                        day_of_week: fields[4].clone(),
-                    },
-                ))
-            } else {
-                Err(nom::Err::Incomplete(nom::Needed::Unknown))
-            }
-        }
-        Err(e) => Err(e),
+impl fmt::Display for When {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} {} {} {} {}",
+            self.minute, self.hour, self.day_of_month, self.month, self.day_of_week
+        )
     }
 }
 
+fn parse_when(input: &str) -> nom::IResult<&str, When> {
+    do_parse!(
+        input,
+        minute: parse_field
+            >> space1
+            >> hour: parse_field
+            >> space1
+            >> day_of_month: parse_field
+            >> space1
+            >> month: call!(parse_named_field, &MONTH_NAMES, FIRST_MONTH)
+            >> space1
+            >> day_of_week: call!(parse_named_field, &DAY_OF_WEEK_NAMES, FIRST_DAY_OF_WEEK)
+            >> (When {
+                minute,
+                hour,
+                day_of_month,
+                month,
+                day_of_week,
+            })
+    )
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub enum Field {
     Value(usize),
     Range(usize, usize, Option<usize>), // begin, end, step
-    List(Vec<(usize, Option<usize>)>),
-    Star(Option<usize>), // step
+    List(Vec<Field>),                   // any of the other variants
+    Star(Option<usize>),                // step
 }
 
 impl Field {
-    pub fn validate(&self, lower_bound: usize, upper_bound: usize) -> Result<(), Error> {
+    pub fn validate(&self, lower_bound: usize, upper_bound: usize) -> Result<(), ScheduleError> {
+        self.check(lower_bound, upper_bound, false)
+    }
+
+    pub fn validate_wrapping(&self, lower_bound: usize, upper_bound: usize) -> Result<(), ScheduleError> {
+        self.check(lower_bound, upper_bound, true)
+    }
+
+    /// Whether `value` is selected by this field. `lower_bound` and
+    /// `upper_bound` give the first and last value of the field: stars step
+    /// from the former, and a range whose start comes after its end wraps
+    /// from the latter back around to the former, stepping across the wrap.
+    pub fn contains(&self, value: usize, lower_bound: usize, upper_bound: usize) -> bool {
+        match self {
+            Field::Value(v) => *v == value,
+            Field::Range(start, end, step) => {
+                let offset = if start <= end {
+                    if value < *start || value > *end {
+                        return false;
+                    }
+                    value - start
+                } else if value >= *start {
+                    value - start
+                } else if value <= *end {
+                    // a start past the last value is refused by validate, but
+                    // can still be asked about
+                    match (value + upper_bound + 1 - lower_bound).checked_sub(*start) {
+                        Some(offset) => offset,
+                        None => return false,
+                    }
+                } else {
+                    return false;
+                };
+                offset.is_multiple_of(step.unwrap_or(1))
+            }
+            Field::List(items) => items
+                .iter()
+                .any(|item| item.contains(value, lower_bound, upper_bound)),
+            Field::Star(step) => {
+                value >= lower_bound && (value - lower_bound).is_multiple_of(step.unwrap_or(1))
+            }
+        }
+    }
+
+    pub fn is_star(&self) -> bool {
+        matches!(self, Field::Star(_))
+    }
+
+    fn check(&self, lower_bound: usize, upper_bound: usize, wrap: bool) -> Result<(), ScheduleError> {
         match self {
             Field::Value(value) => {
                 if *value < lower_bound {
-                    bail!(
+                    invalid!(
                         "value too low (got {} but expected no less than {})",
                         value,
                         lower_bound
                     );
                 }
                 if *value > upper_bound {
-                    bail!(
+                    invalid!(
                         "value too high (got {} but expected no more than {})",
                         value,
                         upper_bound
@@ -176,86 +378,45 @@ impl Field {
                 }
             }
             Field::Range(start, end, maybe_step) => {
-                if start > end {
-                    bail!(
+                if start > end && !wrap {
+                    invalid!(
                         "range out of order (start {} came after end {})",
                         start,
                         end
                     );
                 }
-                if let Some(step) = maybe_step {
-                    if (start + step) >= *end {
-                        bail!(
-                            "step too big (range only covers {} but step was {})",
-                            end - start,
-                            step
+                // either end can be out of bounds either way once ranges wrap
+                for (which, value) in &[("start", start), ("end", end)] {
+                    if **value < lower_bound {
+                        invalid!(
+                            "range {} too low (got {} but expected no less than {})",
+                            which,
+                            value,
+                            lower_bound
+                        );
+                    }
+                    if **value > upper_bound {
+                        invalid!(
+                            "range {} too high (got {} but expected no more than {})",
+                            which,
+                            value,
+                            upper_bound
                         );
                     }
                 }
-                if *start < lower_bound {
-                    bail!(
-                        "range start too low (got {} but expected no less than {})",
-                        start,
-                        lower_bound
-                    );
-                }
-                if *end > upper_bound {
-                    bail!(
-                        "range end too high (got {} but expected no more than {})",
-                        end,
-                        upper_bound
-                    );
+                if let Some(step) = maybe_step {
+                    validate_step(*step, upper_bound)?;
                 }
             }
             Field::Star(None) => (),
-            Field::Star(Some(step)) => {
-                if *step > upper_bound - lower_bound {
-                    bail!(
-                        "step too big (field only covers {} but step was {})",
-                        upper_bound - lower_bound,
-                        step
-                    );
-                }
-            }
+            Field::Star(Some(step)) => validate_step(*step, upper_bound)?,
             Field::List(items) => {
                 for item in items {
-                    match item {
-                        (value, None) => {
-                            if *value < lower_bound {
-                                bail!(
-                                    "list value too low (got {} but expected no less than {})",
-                                    value,
-                                    lower_bound
-                                );
-                            }
-                            if *value > upper_bound {
-                                bail!(
-                                    "list value too high (got {} but expected no more than {})",
-                                    value,
-                                    upper_bound
-                                );
-                            }
-                        }
-                        (start, Some(end)) => {
-                            if start > end {
-                                bail!(
-                                    "list range out of order (start {} came after end {})",
-                                    start,
-                                    end
-                                );
-                            }
-                            if *start < lower_bound {
-                                bail!("list range start too low (got {} but expected no less than {})", start, lower_bound);
-                            }
-                            if *end > upper_bound {
-                                bail!(
-                                    "list range end too high (got {} but expected no more than {})",
-                                    end,
-                                    upper_bound
-                                );
-                            }
-                        }
+                    if let Field::List(_) = item {
+                        invalid!("list cannot contain another list");
                     }
+                    item.check(lower_bound, upper_bound, wrap)
+                        .map_err(|e| ScheduleError(format!("list {}", e)))?;
                 }
             }
         }
@@ -263,44 +424,147 @@ impl Field {
     }
 }
 
-named!(integer<&str, usize>, map_res!(digit1, |s| usize::from_str(s)));
+/// A list inside a list, which `validate` refuses, prints as one flat list.
+impl fmt::Display for Field {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let step = |f: &mut fmt::Formatter, step: &Option<usize>| match step {
+            Some(step) => write!(f, "/{}", step),
+            None => Ok(()),
+        };
+        match self {
+            Field::Value(value) => write!(f, "{}", value),
+            Field::Range(start, end, maybe_step) => {
+                write!(f, "{}-{}", start, end)?;
+                step(f, maybe_step)
+            }
+            Field::List(items) => {
+                for (index, item) in items.iter().enumerate() {
+                    if index > 0 {
+                        f.write_str(",")?;
+                    }
+                    item.fmt(f)?;
+                }
+                Ok(())
+            }
+            Field::Star(maybe_step) => {
+                f.write_str("*")?;
+                step(f, maybe_step)
+            }
+        }
+    }
+}
+
+/*
+ * cronie refuses a zero step and any step larger than the highest value of
+ * the field; anything else is fine, even if it only ever selects the first
+ * value of the range (e.g. 0-10/20 is just 0).
+ */
+fn validate_step(step: usize, upper_bound: usize) -> Result<(), ScheduleError> {
+    if step == 0 {
+        invalid!("step must be at least 1");
+    }
+    if step > upper_bound {
+        invalid!(
+            "step too big (got {} but expected no more than {})",
+            step,
+            upper_bound
+        );
+    }
+    Ok(())
+}
+
+named!(integer<&str, usize>, map_res!(digit1, usize::from_str));
 
 named!(step<&str, Option<usize>>, opt!(preceded!(char!('/'), integer)));
 
-named!(parse_range<&str, Field>, complete!(map!(
-    tuple!(separated_pair!(integer, char!('-'), integer), step),
-    |((begin, end), step)| Field::Range(begin, end, step)
-)));
-
-named!(parse_field<&str, Field>, alt!(
-    map!(preceded!(char!('*'), step), |opt| Field::Star(opt)) |
-    map_opt!(
-        separated_list_complete!(
-            char!(','),
-            alt!(
-                map_opt!(parse_range, |f| {
-                    match f {
-                        Field::Range(begin, end, None) => Some((begin, Some(end))),
-                        _ => None
-                    }
-                }) |
-                map!(integer, |i| (i, None))
+/*
+ * Months and days of the week may also be given by the first three letters
+ * of their English name, in any case; `names[0]` stands for `first`.
+ */
+const MONTH_NAMES: [&str; 12] = [
+    "jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec",
+];
+const DAY_OF_WEEK_NAMES: [&str; 7] = ["sun", "mon", "tue", "wed", "thu", "fri", "sat"];
+
+fn name<'a>(input: &'a str, names: &[&str], first: usize) -> nom::IResult<&'a str, usize> {
+    let (candidate, rest) = match (input.get(..3), input.get(3..)) {
+        (Some(candidate), Some(rest)) => (candidate, rest),
+        _ => return Err(nom::Err::Error(error_position!(input, nom::ErrorKind::Tag))),
+    };
+    if rest.starts_with(|c: char| c.is_ascii_alphabetic()) {
+        return Err(nom::Err::Error(error_position!(input, nom::ErrorKind::Tag)));
+    }
+    match names
+        .iter()
+        .position(|name| name.eq_ignore_ascii_case(candidate))
+    {
+        Some(index) => Ok((rest, first + index)),
+        None => Err(nom::Err::Error(error_position!(input, nom::ErrorKind::Tag))),
+    }
+}
+
+fn number<'a>(input: &'a str, names: &[&str], first: usize) -> nom::IResult<&'a str, usize> {
+    alt!(input, integer | call!(name, names, first))
+}
+
+fn parse_range<'a>(input: &'a str, names: &[&str], first: usize) -> nom::IResult<&'a str, Field> {
+    complete!(
+        input,
+        map!(
+            tuple!(
+                separated_pair!(
+                    call!(number, names, first),
+                    char!('-'),
+                    call!(number, names, first)
+                ),
+                step
+            ),
+            |((begin, end), step)| Field::Range(begin, end, step)
+        )
+    )
+}
+
+/*
+ * Steps only go with stars and ranges. Vixie cron refuses `5/10` too, so
+ * rather than leave the `/10` to start the command, it is not an item.
+ */
+fn parse_item<'a>(input: &'a str, names: &[&str], first: usize) -> nom::IResult<&'a str, Field> {
+    alt!(
+        input,
+        map!(preceded!(char!('*'), step), Field::Star)
+            | call!(parse_range, names, first)
+            | map!(
+                terminated!(call!(number, names, first), not!(complete!(char!('/')))),
+                Field::Value
             )
-        ),
-        |v: Vec<(usize, Option<usize>)>| {
-            if v.len() > 1 {
-                Some(Field::List(v))
+    )
+}
+
+fn parse_named_field<'a>(
+    input: &'a str,
+    names: &[&str],
+    first: usize,
+) -> nom::IResult<&'a str, Field> {
+    map!(
+        input,
+        separated_nonempty_list_complete!(char!(','), call!(parse_item, names, first)),
+        |mut items: Vec<Field>| {
+            if items.len() == 1 {
+                items.remove(0)
             } else {
-                None
+                Field::List(items)
             }
-        }) |
-    parse_range |
-    map!(integer, |i| Field::Value(i))
-));
+        }
+    )
+}
+
+named!(parse_field<&str, Field>, call!(parse_named_field, &[], 0));
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::test_helpers::below;
+    use quickcheck::{quickcheck, Arbitrary, Gen};
 
     #[test]
     fn parses_all_stars() {
@@ -355,7 +619,7 @@ mod tests {
     fn list() {
         assert_parses_to!(
             parse_field("1,2,3 "),
-            Field::List(vec![(1, None), (2, None), (3, None)]),
+            Field::List(vec![Field::Value(1), Field::Value(2), Field::Value(3)]),
             " "
         )
     }
@@ -364,8 +628,374 @@ mod tests {
     fn range_list() {
         assert_parses_to!(
             parse_field("1-4,2-5,3-6,7 "),
-            Field::List(vec![(1, Some(4)), (2, Some(5)), (3, Some(6)), (7, None)]),
+            Field::List(vec![
+                Field::Range(1, 4, None),
+                Field::Range(2, 5, None),
+                Field::Range(3, 6, None),
+                Field::Value(7)
+            ]),
+            " "
+        )
+    }
+
+    #[test]
+    fn stepped_range_list() {
+        assert_parses_to!(
+            parse_field("0-10/2,30-40/5 "),
+            Field::List(vec![Field::Range(0, 10, Some(2)), Field::Range(30, 40, Some(5))]),
+            " "
+        )
+    }
+
+    #[test]
+    fn star_list() {
+        assert_parses_to!(
+            parse_field("*/20,5 "),
+            Field::List(vec![Field::Star(Some(20)), Field::Value(5)]),
             " "
         )
     }
+
+    #[test]
+    fn value_with_step_is_refused() {
+        assert!(parse_field("5/10 ").is_err());
+        assert!(parse_named_field("fri/2 ", &DAY_OF_WEEK_NAMES, FIRST_DAY_OF_WEEK).is_err());
+        assert!(parse_when("* * * * 5/10 echo hi").is_err());
+        assert!(parse_when("1,5/10 * * * * echo hi").is_err());
+    }
+
+    #[test]
+    fn validate_value() {
+        assert!(Field::Value(0).validate(0, 59).is_ok());
+        assert!(Field::Value(59).validate(0, 59).is_ok());
+        assert!(Field::Value(60).validate(0, 59).is_err());
+        assert!(Field::Value(0).validate(1, 31).is_err());
+    }
+
+    #[test]
+    fn validate_range() {
+        assert!(Field::Range(0, 59, None).validate(0, 59).is_ok());
+        assert!(Field::Range(5, 5, None).validate(0, 59).is_ok());
+        assert!(Field::Range(10, 5, None).validate(0, 59).is_err());
+        assert!(Field::Range(0, 60, None).validate(0, 59).is_err());
+        assert!(Field::Range(0, 5, None).validate(1, 31).is_err());
+    }
+
+    #[test]
+    fn validate_range_with_step() {
+        assert!(Field::Range(0, 59, Some(30)).validate(0, 59).is_ok());
+        assert!(Field::Range(0, 10, Some(20)).validate(0, 59).is_ok());
+        assert!(Field::Range(0, 59, Some(59)).validate(0, 59).is_ok());
+        assert!(Field::Range(0, 59, Some(60)).validate(0, 59).is_err());
+        assert!(Field::Range(0, 59, Some(0)).validate(0, 59).is_err());
+        assert!(Field::Range(5, 64, Some(30)).validate(0, 59).is_err());
+    }
+
+    #[test]
+    fn validate_star_with_step() {
+        assert!(Field::Star(None).validate(0, 59).is_ok());
+        assert!(Field::Star(Some(15)).validate(0, 59).is_ok());
+        assert!(Field::Star(Some(23)).validate(0, 23).is_ok());
+        assert!(Field::Star(Some(24)).validate(0, 23).is_err());
+        assert!(Field::Star(Some(0)).validate(0, 23).is_err());
+    }
+
+    #[test]
+    fn validate_list() {
+        let list = Field::List(vec![
+            Field::Value(1),
+            Field::Range(10, 20, None),
+            Field::Range(30, 40, Some(5)),
+            Field::Star(Some(15)),
+        ]);
+        assert!(list.validate(0, 59).is_ok());
+        assert!(Field::List(vec![Field::Value(1), Field::Value(60)])
+            .validate(0, 59)
+            .is_err());
+        assert!(Field::List(vec![Field::Value(1), Field::Range(9, 3, None)])
+            .validate(0, 59)
+            .is_err());
+        assert!(Field::List(vec![Field::Value(1), Field::Star(Some(0))])
+            .validate(0, 59)
+            .is_err());
+        assert!(Field::List(vec![Field::List(vec![Field::Value(1)])])
+            .validate(0, 59)
+            .is_err());
+    }
+
+    #[test]
+    fn month_names() {
+        assert_parses_to!(
+            parse_named_field("jan,Mar-MAY ", &MONTH_NAMES, FIRST_MONTH),
+            Field::List(vec![Field::Value(1), Field::Range(3, 5, None)]),
+            " "
+        )
+    }
+
+    #[test]
+    fn day_of_week_names() {
+        assert_parses_to!(
+            parse_named_field("FRI-MON/2 ", &DAY_OF_WEEK_NAMES, FIRST_DAY_OF_WEEK),
+            Field::Range(5, 1, Some(2)),
+            " "
+        )
+    }
+
+    #[test]
+    fn long_names_are_not_names() {
+        assert!(parse_named_field("monday ", &DAY_OF_WEEK_NAMES, FIRST_DAY_OF_WEEK).is_err())
+    }
+
+    #[test]
+    fn names_only_in_their_fields() {
+        assert!(parse_field("mon ").is_err())
+    }
+
+    #[test]
+    fn parses_names_in_when() {
+        assert_parses_to!(
+            parse_when("0 0 * dec sun "),
+            When {
+                minute: Field::Value(0),
+                hour: Field::Value(0),
+                day_of_month: Field::Star(None),
+                month: Field::Value(12),
+                day_of_week: Field::Value(0)
+            },
+            " "
+        )
+    }
+
+    #[test]
+    fn when_stops_after_five_fields() {
+        let (rest, _) = parse_when("* * * * * 5 o'clock").unwrap();
+        assert_eq!(rest, " 5 o'clock");
+    }
+
+    #[test]
+    fn validate_wrapping_range() {
+        assert!(Field::Range(22, 2, None).validate(0, 23).is_err());
+        assert!(Field::Range(22, 2, None).validate_wrapping(0, 23).is_ok());
+        assert!(Field::Range(22, 24, None).validate_wrapping(0, 23).is_err());
+        assert!(Field::Range(99, 2, None).validate_wrapping(0, 23).is_err());
+        assert!(Field::Range(5, 99, None).validate_wrapping(0, 23).is_err());
+        assert!(Field::Range(24, 0, None).validate_wrapping(0, 23).is_err());
+        assert!(Field::Range(3, 0, None).validate_wrapping(1, 31).is_err());
+        assert!(Field::List(vec![Field::Range(22, 2, Some(2))])
+            .validate_wrapping(0, 23)
+            .is_ok());
+    }
+
+    #[test]
+    fn contains_steps_through_ranges_and_stars() {
+        let field = Field::List(vec![Field::Range(0, 10, Some(5)), Field::Star(Some(20))]);
+        let hits: Vec<usize> = (0..60).filter(|v| field.contains(*v, 0, 59)).collect();
+        assert_eq!(hits, vec![0, 5, 10, 20, 40]);
+    }
+
+    #[test]
+    fn contains_wraps_reversed_ranges() {
+        let hits: Vec<usize> = (0..24)
+            .filter(|v| Field::Range(22, 2, None).contains(*v, 0, 23))
+            .collect();
+        assert_eq!(hits, vec![0, 1, 2, 22, 23]);
+    }
+
+    #[test]
+    fn contains_steps_across_the_wrap() {
+        let hits: Vec<usize> = (0..24)
+            .filter(|v| Field::Range(21, 3, Some(2)).contains(*v, 0, 23))
+            .collect();
+        assert_eq!(hits, vec![1, 3, 21, 23]);
+    }
+
+    #[test]
+    fn contains_nothing_wrapped_from_past_the_end() {
+        assert!(!(0..24).any(|v| Field::Range(99, 2, None).contains(v, 0, 23)));
+    }
+
+    fn when(input: &str) -> When {
+        let (_, when) = parse_when(input).unwrap();
+        when
+    }
+
+    fn moment(day_of_month: usize, day_of_week: usize, hour: usize, minute: usize) -> Moment {
+        Moment {
+            year: 2018,
+            month: 10,
+            day_of_month,
+            day_of_week,
+            hour,
+            minute,
+        }
+    }
+
+    #[test]
+    fn matches_minute_and_hour() {
+        let when = when("*/15 9-17 * * * ");
+        assert!(when.matches(&moment(1, 1, 9, 45)));
+        assert!(!when.matches(&moment(1, 1, 9, 46)));
+        assert!(!when.matches(&moment(1, 1, 18, 0)));
+    }
+
+    #[test]
+    fn matches_either_day_when_neither_is_starred() {
+        let when = when("0 0 13 * fri ");
+        assert!(when.matches(&moment(13, 2, 0, 0)));
+        assert!(when.matches(&moment(12, 5, 0, 0)));
+        assert!(!when.matches(&moment(12, 4, 0, 0)));
+    }
+
+    #[test]
+    fn matches_both_days_when_one_is_starred() {
+        let when = when("0 0 */2 * fri ");
+        assert!(when.matches(&moment(13, 5, 0, 0)));
+        assert!(!when.matches(&moment(12, 5, 0, 0)));
+        assert!(!when.matches(&moment(13, 4, 0, 0)));
+    }
+
+    #[test]
+    fn matches_sunday_as_seven() {
+        assert!(when("0 0 * * 7 ").matches(&moment(14, 0, 0, 0)));
+        assert!(when("0 0 * * 5-7 ").matches(&moment(14, 0, 0, 0)));
+    }
+
+    #[test]
+    fn matches_days_of_week_across_the_wrap() {
+        let when = when("0 0 * * FRI-MON/2 ");
+        let days: Vec<usize> = (0..7)
+            .filter(|d| when.matches(&moment(1, *d, 0, 0)))
+            .collect();
+        assert_eq!(days, vec![0, 5]);
+    }
+
+    #[test]
+    fn wildcards() {
+        assert!(when("* 3 * * * ").is_wildcard());
+        assert!(when("0 */2 * * * ").is_wildcard());
+        assert!(!when("0 3 * * * ").is_wildcard());
+    }
+
+    fn schedule(input: &str) -> Schedule {
+        let (_, schedule) = Schedule::parse(input).unwrap();
+        schedule
+    }
+
+    #[test]
+    fn next_after_within_the_hour() {
+        // 1970-01-01T00:07, a Thursday
+        assert_eq!(schedule("*/15 * * * * ").next_after(7), Some(15));
+        assert_eq!(schedule("*/15 * * * * ").next_after(15), Some(30));
+        assert_eq!(schedule("* * * * * ").next_after(15), Some(16));
+    }
+
+    #[test]
+    fn next_after_skips_days() {
+        // the first Monday is 1970-01-05
+        assert_eq!(
+            schedule("30 9 * * mon ").next_after(0),
+            Some(4 * 24 * 60 + 9 * 60 + 30)
+        );
+    }
+
+    #[test]
+    fn next_after_finds_leap_days() {
+        let next = schedule("0 0 29 2 * ").next_after(0).unwrap();
+        let moment = Moment::at(next);
+        assert_eq!((moment.year, moment.month, moment.day_of_month), (1972, 2, 29));
+    }
+
+    #[test]
+    fn next_after_gives_up_on_impossible_dates() {
+        assert_eq!(schedule("0 0 30 2 * ").next_after(0), None);
+        assert_eq!(schedule("@reboot").next_after(0), None);
+    }
+
+    #[test]
+    fn validate_stepped_when() {
+        let (_, schedule) = Schedule::parse("0-59/30 0-10/2,12-22/5 * * * ").unwrap();
+        assert!(schedule.validate().is_ok());
+    }
+
+    #[test]
+    fn from_str_takes_whole_valid_schedules() {
+        assert_eq!("@daily".parse(), Ok(schedule("0 0 * * * ")));
+        assert_eq!(" */5 * * * 1-5\n".parse(), Ok(schedule("*/5 * * * 1-5 ")));
+        assert_eq!(
+            "60 * * * *".parse::<Schedule>().unwrap_err().to_string(),
+            "minute value too high (got 60 but expected no more than 59)"
+        );
+        assert!("* * * * * echo".parse::<Schedule>().is_err());
+        assert!("* * *".parse::<Schedule>().is_err());
+    }
+
+    #[test]
+    fn upcoming_counts_on_from_next_after() {
+        let times: Vec<Minute> = schedule("0 */8 * * * ").upcoming(0).take(4).collect();
+        assert_eq!(times, vec![8 * 60, 16 * 60, 24 * 60, 32 * 60]);
+        assert_eq!(schedule("0 0 30 2 * ").upcoming(0).next(), None);
+    }
+
+    #[test]
+    fn displays_canonically() {
+        assert_eq!(schedule("@daily").to_string(), "0 0 * * *");
+        assert_eq!(schedule("@reboot").to_string(), "@reboot");
+        assert_eq!(
+            schedule("*/15 9-17/2 * jan-MAR mon,5-6,*/2 ").to_string(),
+            "*/15 9-17/2 * 1-3 1,5-6,*/2"
+        );
+    }
+
+    fn arbitrary_item<G: Gen>(g: &mut G, first: usize, last: usize) -> Field {
+        let value = |g: &mut G| first + below(g, last - first + 1);
+        let step = |g: &mut G| {
+            if bool::arbitrary(g) {
+                Some(1 + below(g, last))
+            } else {
+                None
+            }
+        };
+        match below(g, 3) {
+            0 => Field::Value(value(g)),
+            1 => Field::Range(value(g), value(g), step(g)),
+            _ => Field::Star(step(g)),
+        }
+    }
+
+    fn arbitrary_field<G: Gen>(g: &mut G, first: usize, last: usize) -> Field {
+        if below(g, 4) == 0 {
+            let count = 2 + below(g, 3);
+            Field::List((0..count).map(|_| arbitrary_item(g, first, last)).collect())
+        } else {
+            arbitrary_item(g, first, last)
+        }
+    }
+
+    impl Arbitrary for Schedule {
+        fn arbitrary<G: Gen>(g: &mut G) -> Self {
+            if below(g, 10) == 0 {
+                return Schedule::Reboot;
+            }
+            Schedule::When(When {
+                minute: arbitrary_field(g, FIRST_MINUTE, LAST_MINUTE),
+                hour: arbitrary_field(g, FIRST_HOUR, LAST_HOUR),
+                day_of_month: arbitrary_field(g, FIRST_DAY_OF_MONTH, LAST_DAY_OF_MONTH),
+                month: arbitrary_field(g, FIRST_MONTH, LAST_MONTH),
+                day_of_week: arbitrary_field(g, FIRST_DAY_OF_WEEK, LAST_DAY_OF_WEEK),
+            })
+        }
+    }
+
+    quickcheck! {
+        fn display_parses_back(schedule: Schedule) -> bool {
+            match Schedule::parse(&format!("{} ", schedule)) {
+                Ok((" ", parsed)) => parsed == schedule,
+                _ => false,
+            }
+        }
+
+        fn valid_schedules_come_back_from_str(schedule: Schedule) -> bool {
+            schedule.validate().is_err() || schedule.to_string().parse() == Ok(schedule)
+        }
+    }
 }
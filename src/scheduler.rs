@@ -0,0 +1,216 @@
+use crondy::clock::Minute;
+use log::debug;
+
+/// How the clock moved since the scheduler last looked.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Pulse {
+    /// A minute on, as it should be.
+    Tick,
+    /// A few minutes on: we woke up late.
+    Late(Minute),
+    /// Up to three hours on, as when DST begins.
+    Forward(Minute),
+    /// Up to three hours back (or not at all), as when DST ends.
+    Back(Minute),
+    /// Anything else.
+    Jump(Minute),
+}
+
+/*
+ * too many clocks, not enough time (Al. Einstein)
+ * These clocks are in minutes since the epoch, on the local wall clock
+ * (see clock.rs).
+ * virtual_time: is the time it *would* be if we woke up
+ * promptly and nobody ever changed the clock. It is
+ * monotonically increasing... unless a timejump happens.
+ * At the top of the loop, all jobs for 'virtual_time' have run.
+ * time_running: is the time we last awakened.
+ * clock_time: is the time when the clock was last read.
+ */
+#[derive(Debug)]
+pub struct Scheduler {
+    virtual_time: Minute,
+    time_running: Minute,
+}
+
+impl Scheduler {
+    pub fn new(now: Minute) -> Self {
+        Scheduler {
+            virtual_time: now,
+            time_running: now,
+        }
+    }
+
+    /// The minute the scheduler last woke up in; once the clock shows
+    /// another, it is time to `advance`.
+    pub fn time_running(&self) -> Minute {
+        self.time_running
+    }
+
+    /*
+     * Catch up with the clock, which now shows `clock_time`, calling
+     * `run(minute, do_wild, do_non_wild)` for the jobs that are due (see
+     * `Crontab::find_jobs`). `clock` reads the clock again, for when going
+     * through missed minutes takes a while. Returns how the clock moved and
+     * what it showed last.
+     */
+    pub fn advance(
+        &mut self,
+        mut clock_time: Minute,
+        clock: &mut dyn FnMut() -> Minute,
+        run: &mut dyn FnMut(Minute, bool, bool),
+    ) -> (Pulse, Minute) {
+        self.time_running = clock_time;
+        let time_running = self.time_running;
+
+        /*
+         * ... calculate how the current time differs from
+         * our virtual clock. Classify the change into one
+         * of 4 cases
+         */
+        let time_diff = time_running - self.virtual_time;
+        debug!(
+            "pulse: {} = {} - {}",
+            time_diff, time_running, self.virtual_time
+        );
+
+        // shortcut for the most common case
+        let pulse = if time_diff == 1 {
+            self.virtual_time = time_running;
+            run(self.virtual_time, true, true);
+            Pulse::Tick
+        } else if time_diff > 0 && time_diff <= 5 {
+            /*
+             * case 1: time_diff is a small positive number
+             * (wokeup late) run jobs for each virtual minute
+             * until caught up.
+             */
+            debug!("normal case, {} minutes to go", time_diff);
+            while self.virtual_time < time_running {
+                self.virtual_time += 1;
+                run(self.virtual_time, true, true);
+            }
+            Pulse::Late(time_diff)
+        } else if time_diff > 5 && time_diff <= 3 * 60 {
+            /*
+             * case 2: time_diff is a medium-sized positive number,
+             * for example because we went to DST run wildcard
+             * jobs once, then run any fixed-time jobs that would
+             * otherwise be skipped if we use up our minute
+             * (possible, if there are a lot of jobs to run) go
+             * around the loop again so that wildcard jobs have
+             * a chance to run, and we do our housekeeping
+             */
+            debug!("DST begins, {} minutes to go", time_diff);
+            // run wildcard jobs for current minute
+            run(time_running, true, false);
+
+            // run fixed-time jobs for each minute missed
+            while self.virtual_time < time_running && clock_time == time_running {
+                self.virtual_time += 1;
+                run(self.virtual_time, false, true);
+                clock_time = clock();
+            }
+            Pulse::Forward(time_diff)
+        } else if time_diff <= 0 && time_diff > -3 * 60 {
+            /*
+             * case 3: time_diff is a small or medium-sized
+             * negative num, eg. because of DST ending just run
+             * the wildcard jobs. The fixed-time jobs probably
+             * have already run, and should not be repeated
+             * virtual time does not change until we are caught up
+             */
+            debug!("DST ends, {} minutes to go", -time_diff);
+            run(time_running, true, false);
+            Pulse::Back(-time_diff)
+        } else {
+            /*
+             * other: time has changed a *lot*,
+             * jump virtual time, and run everything
+             */
+            debug!("clock jumped");
+            self.virtual_time = time_running;
+            run(time_running, true, true);
+            Pulse::Jump(time_diff)
+        };
+        (pulse, clock_time)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /*
+     * Advance a scheduler that last woke up at minute 100 to `now`, with a
+     * clock that stands still, and list the calls to `run`.
+     */
+    fn advance(virtual_time: Minute, now: Minute) -> (Pulse, Vec<(Minute, bool, bool)>) {
+        let mut scheduler = Scheduler::new(100);
+        scheduler.virtual_time = virtual_time;
+        let mut runs = Vec::new();
+        let (pulse, clock_time) =
+            scheduler.advance(now, &mut || now, &mut |minute, wild, fixed| {
+                runs.push((minute, wild, fixed))
+            });
+        assert_eq!(clock_time, now);
+        assert_eq!(scheduler.time_running(), now);
+        (pulse, runs)
+    }
+
+    #[test]
+    fn ticks() {
+        assert_eq!(advance(100, 101), (Pulse::Tick, vec![(101, true, true)]));
+    }
+
+    #[test]
+    fn runs_every_minute_when_late() {
+        assert_eq!(
+            advance(100, 103),
+            (
+                Pulse::Late(3),
+                vec![(101, true, true), (102, true, true), (103, true, true)]
+            )
+        );
+    }
+
+    #[test]
+    fn runs_wildcards_once_and_fixed_times_for_every_minute_when_forward() {
+        let (pulse, runs) = advance(100, 160);
+        assert_eq!(pulse, Pulse::Forward(60));
+        assert_eq!(runs.len(), 61);
+        assert_eq!(runs[0], (160, true, false));
+        assert_eq!(runs[1], (101, false, true));
+        assert_eq!(runs[60], (160, false, true));
+    }
+
+    #[test]
+    fn runs_only_wildcards_when_back() {
+        assert_eq!(advance(160, 101), (Pulse::Back(59), vec![(101, true, false)]));
+    }
+
+    #[test]
+    fn jumps() {
+        assert_eq!(advance(100, 10_000), (Pulse::Jump(9900), vec![(10_000, true, true)]));
+    }
+
+    #[test]
+    fn stops_going_forward_when_the_clock_moves_on() {
+        let mut scheduler = Scheduler::new(100);
+        let mut reads = 0;
+        let mut runs = 0;
+        let (_, clock_time) = scheduler.advance(
+            160,
+            &mut || {
+                reads += 1;
+                if reads < 3 {
+                    160
+                } else {
+                    161
+                }
+            },
+            &mut |_, _, _| runs += 1,
+        );
+        assert_eq!((runs, clock_time), (4, 161));
+    }
+}
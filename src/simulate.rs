@@ -0,0 +1,350 @@
+use std::mem;
+
+use crondy::clock::{self, Minute, Moment};
+use crondy::crontab::Crontab;
+use crondy::options;
+use failure::{bail, format_err, Error};
+
+use crate::scheduler::{Pulse, Scheduler};
+
+/*
+ * What `crondy simulate` was asked for:
+ *
+ *     crondy simulate --from TIME --to TIME [--timezone TZ]
+ *                     [--jump [+|-]DURATION@TIME]... [CRONTAB]
+ *
+ * Each --jump sets the clock forward or back by DURATION once it shows
+ * TIME, as if someone had changed it. CRONTAB is a file, or - for stdin;
+ * without it, $CRONTAB or /etc/crontab.
+ */
+#[derive(Debug, Default, PartialEq)]
+pub struct Simulation {
+    pub from: Minute,
+    pub to: Minute,
+    pub jumps: Vec<Jump>,
+    pub timezone: Option<String>,
+    pub crontab: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Jump {
+    pub at: Minute,
+    pub by: Minute,
+}
+
+impl Simulation {
+    pub fn parse(args: &[String]) -> Result<Self, Error> {
+        let mut from = None;
+        let mut to = None;
+        let mut simulation = Simulation::default();
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            if !arg.starts_with("--") {
+                if simulation.crontab.is_some() {
+                    bail!("only one crontab at a time, got {:?} as well", arg);
+                }
+                simulation.crontab = Some(arg.clone());
+                continue;
+            }
+            let (name, inline) = match arg.find('=') {
+                Some(at) => (&arg[..at], Some(arg[at + 1..].to_owned())),
+                None => (arg.as_str(), None),
+            };
+            let value = match inline.or_else(|| args.next().cloned()) {
+                Some(value) => value,
+                None => bail!("{} needs a value", name),
+            };
+            match name {
+                "--from" => from = Some(parse_time(name, &value)?),
+                "--to" => to = Some(parse_time(name, &value)?),
+                "--jump" => simulation.jumps.push(parse_jump(&value)?),
+                "--timezone" | "--tz" => simulation.timezone = Some(value),
+                _ => bail!("unknown option {:?}", name),
+            }
+        }
+        match (from, to) {
+            (Some(from), Some(to)) if from < to => {
+                simulation.from = from;
+                simulation.to = to;
+            }
+            (Some(_), Some(_)) => bail!("--to must be after --from"),
+            _ => bail!("--from and --to are required"),
+        }
+        Ok(simulation)
+    }
+
+    /*
+     * Drive the scheduler from `from` to `to` the way the daemon would,
+     * with a clock that moves a minute at a time, and describe everything
+     * it would have started. `wall_clock` turns UTC into local time, like
+     * `clock::wall_clock`.
+     */
+    pub fn run(&self, crontab: &Crontab, wall_clock: &dyn Fn(i64) -> i64) -> String {
+        let mut out = String::new();
+        let mut clock = VirtualClock::new(self.from, &self.jumps, wall_clock);
+        for entry in crontab.reboot_jobs() {
+            out.push_str(&format!(
+                "{}  {}: {}\n",
+                Moment::at(clock.now()),
+                entry.location(),
+                entry.source()
+            ));
+        }
+
+        let mut scheduler = Scheduler::new(clock.now());
+        let mut previous = Pulse::Tick;
+        // however the clock is moved about, stop eventually
+        let jumped: Minute = self.jumps.iter().map(|jump| jump.by.abs()).sum();
+        let mut ticks_left = (self.to - self.from + jumped) * 2 + 24 * 60;
+        while clock.now() < self.to && ticks_left > 0 {
+            ticks_left -= 1;
+            if let Some(jump) = clock.tick() {
+                out.push_str(&format!(
+                    "{}  clock set {} {} minutes\n",
+                    Moment::at(clock.now()),
+                    if jump.by < 0 { "back" } else { "forward" },
+                    jump.by.abs()
+                ));
+            }
+            let now = clock.now();
+            if now == scheduler.time_running() {
+                continue;
+            }
+
+            let mut due = Vec::new();
+            let (pulse, _) =
+                scheduler.advance(now, &mut || clock.now(), &mut |minute, wild, fixed| {
+                    for entry in crontab.find_jobs(&Moment::at(minute), wild, fixed) {
+                        due.push((minute, entry.location(), entry.source().to_owned()));
+                    }
+                });
+            if mem::discriminant(&pulse) != mem::discriminant(&previous) {
+                if let Some(description) = describe(pulse) {
+                    out.push_str(&format!("{}  {}\n", Moment::at(now), description));
+                }
+            }
+            previous = pulse;
+            for (minute, location, source) in due {
+                let late = if minute == now {
+                    String::new()
+                } else {
+                    format!(" (due {})", Moment::at(minute))
+                };
+                out.push_str(&format!("{}  {}{}: {}\n", Moment::at(now), location, late, source));
+            }
+        }
+        out
+    }
+}
+
+fn describe(pulse: Pulse) -> Option<String> {
+    match pulse {
+        Pulse::Tick => None,
+        Pulse::Late(minutes) => Some(format!(
+            "{} minutes passed at once, running each of them",
+            minutes
+        )),
+        Pulse::Forward(minutes) => Some(format!(
+            "clock went forward {} minutes, running wildcard entries once \
+             and fixed-time entries for every minute skipped",
+            minutes
+        )),
+        Pulse::Back(minutes) => Some(format!(
+            "clock went back {} minutes, running only wildcard entries until it catches up",
+            minutes
+        )),
+        Pulse::Jump(minutes) => Some(format!(
+            "clock jumped {} minutes, running what is due now and skipping the rest",
+            minutes
+        )),
+    }
+}
+
+fn parse_time(name: &str, value: &str) -> Result<Minute, Error> {
+    clock::parse_seconds(value)
+        .map(|seconds| seconds.div_euclid(60))
+        .ok_or_else(|| format_err!("{}: expected YYYY-MM-DD[THH:MM], got {:?}", name, value))
+}
+
+/// `[+|-]DURATION@TIME`
+fn parse_jump(value: &str) -> Result<Jump, Error> {
+    let (by, at) = match value.find('@') {
+        Some(index) => (&value[..index], &value[index + 1..]),
+        None => bail!("--jump: expected [+|-]DURATION@TIME, got {:?}", value),
+    };
+    let (sign, by) = match by.chars().next() {
+        Some('-') => (-1, &by[1..]),
+        Some('+') => (1, &by[1..]),
+        _ => (1, by),
+    };
+    let by = options::parse_duration("--jump", by)?.as_secs() as Minute / 60;
+    Ok(Jump {
+        at: parse_time("--jump", at)?,
+        by: sign * by,
+    })
+}
+
+/*
+ * A clock that runs in UTC a minute per tick, shows local time and can be
+ * set forward or back.
+ */
+struct VirtualClock<'a> {
+    utc: i64, // seconds
+    set: i64, // seconds it has been set forward
+    jumps: Vec<Jump>,
+    wall_clock: &'a dyn Fn(i64) -> i64,
+}
+
+impl<'a> VirtualClock<'a> {
+    fn new(from: Minute, jumps: &[Jump], wall_clock: &'a dyn Fn(i64) -> i64) -> Self {
+        // the UTC time that shows as `from`, as far as there is one
+        let from = from * 60;
+        let offset = wall_clock(from) - from;
+        let offset = wall_clock(from - offset) - (from - offset);
+        let mut jumps = jumps.to_vec();
+        jumps.sort_by_key(|jump| jump.at);
+        VirtualClock {
+            utc: from - offset,
+            set: 0,
+            jumps,
+            wall_clock,
+        }
+    }
+
+    fn now(&self) -> Minute {
+        ((self.wall_clock)(self.utc) + self.set).div_euclid(60)
+    }
+
+    /// Move on a minute, and then make any jump that is due.
+    fn tick(&mut self) -> Option<Jump> {
+        self.utc += 60;
+        let now = self.now();
+        if self.jumps.first().is_some_and(|jump| jump.at <= now) {
+            let jump = self.jumps.remove(0);
+            self.set += jump.by * 60;
+            return Some(jump);
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn simulation(args: &[&str]) -> Simulation {
+        let args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+        Simulation::parse(&args).unwrap()
+    }
+
+    fn utc(seconds: i64) -> i64 {
+        seconds
+    }
+
+    /*
+     * A timezone that is UTC until 1970-01-02T02:00 UTC+0, and UTC+1 from
+     * then until 1970-01-03T02:00 UTC+1.
+     */
+    fn summer(seconds: i64) -> i64 {
+        let begins = (24 + 2) * 3600;
+        let ends = (48 + 1) * 3600;
+        if (begins..ends).contains(&seconds) {
+            seconds + 3600
+        } else {
+            seconds
+        }
+    }
+
+    fn lines(output: String) -> Vec<String> {
+        output.lines().map(str::to_owned).collect()
+    }
+
+    #[test]
+    fn parses_simulations() {
+        let parsed = simulation(&[
+            "--from",
+            "1970-01-01",
+            "--to=1970-01-01T01:00",
+            "--jump",
+            "-2h@1970-01-01T00:30",
+            "tab",
+        ]);
+        assert_eq!(
+            parsed,
+            Simulation {
+                from: 0,
+                to: 60,
+                jumps: vec![Jump { at: 30, by: -120 }],
+                timezone: None,
+                crontab: Some("tab".to_owned()),
+            }
+        );
+    }
+
+    #[test]
+    fn rejects_bad_simulations() {
+        let parse = |args: &[&str]| {
+            Simulation::parse(&args.iter().map(|arg| arg.to_string()).collect::<Vec<_>>())
+        };
+        assert!(parse(&["--from", "1970-01-01"]).is_err());
+        assert!(parse(&["--from", "1970-01-02", "--to", "1970-01-01"]).is_err());
+        assert!(parse(&["--from", "1970-01-01", "--to", "1970-01-02", "--jump", "1h"]).is_err());
+    }
+
+    #[test]
+    fn reports_what_runs() {
+        let tab = Crontab::parse("@reboot boot\n*/20 * * * * often\n").unwrap();
+        let simulation = simulation(&["--from", "1970-01-01", "--to", "1970-01-01T01:00"]);
+        assert_eq!(
+            lines(simulation.run(&tab, &utc)),
+            vec![
+                "1970-01-01T00:00  line 1: @reboot boot",
+                "1970-01-01T00:20  line 2: */20 * * * * often",
+                "1970-01-01T00:40  line 2: */20 * * * * often",
+                "1970-01-01T01:00  line 2: */20 * * * * often",
+            ]
+        );
+    }
+
+    #[test]
+    fn goes_through_dst() {
+        let tab = Crontab::parse("30 2 * * * fixed\n0 * * * * wild\n").unwrap();
+        let simulation = simulation(&["--from", "1970-01-02T01:30", "--to", "1970-01-03T03:00"]);
+        let output = lines(simulation.run(&tab, &summer));
+        let runs: Vec<&String> = output.iter().filter(|line| line.contains("line 1")).collect();
+        // skipped at 02:30 when DST begins, not repeated when it ends
+        assert_eq!(
+            runs,
+            vec![
+                "1970-01-02T03:00  line 1 (due 1970-01-02T02:30): 30 2 * * * fixed",
+                "1970-01-03T02:30  line 1: 30 2 * * * fixed",
+            ]
+        );
+        let wild = output.iter().filter(|line| line.contains("line 2")).count();
+        // every hour from 03:00 to 03:00 the next day, and 01:00 again
+        assert_eq!(wild, 25 + 1);
+        // the clock is read at 01:59 and next at 03:00
+        assert!(output.iter().any(|line| line.contains("clock went forward 61 minutes")));
+        assert!(output.iter().any(|line| line.contains("clock went back 59 minutes")));
+    }
+
+    #[test]
+    fn injects_jumps() {
+        let tab = Crontab::parse("0 * * * * hourly\n").unwrap();
+        let simulation = simulation(&[
+            "--from",
+            "1970-01-01",
+            "--to",
+            "1970-01-02",
+            "--jump",
+            "+10h@1970-01-01T05:30",
+        ]);
+        let output = lines(simulation.run(&tab, &utc));
+        assert!(output.contains(&"1970-01-01T15:30  clock set forward 600 minutes".to_owned()));
+        assert!(output
+            .iter()
+            .any(|line| line.starts_with("1970-01-01T15:30  clock jumped 601 minutes")));
+        // 01:00 to 05:00, then 16:00 to 00:00 the next day
+        assert_eq!(output.iter().filter(|line| line.contains("line 1")).count(), 5 + 9);
+    }
+}
@@ -0,0 +1,201 @@
+use std::collections::{HashMap, VecDeque};
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use crondy::clock::Minute;
+use crondy::crontab::Entry;
+use failure::{format_err, Error};
+
+/*
+ * When each entry that catches up on missed runs was last due, kept in a
+ * file with one "<minute> <entry>" line per entry, where <minute> is the
+ * minute the run was scheduled for (see clock.rs) and <entry> is the entry as
+ * written in the crontab.
+ */
+#[derive(Debug)]
+pub struct LastRuns {
+    path: PathBuf,
+    runs: HashMap<String, Minute>,
+}
+
+impl LastRuns {
+    /// Read the store at `path`; a missing file is an empty store.
+    pub fn load(path: &Path) -> Result<Self, Error> {
+        let mut runs = HashMap::new();
+        let contents = match fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(ref error) if error.kind() == io::ErrorKind::NotFound => String::new(),
+            Err(error) => return Err(format_err!("could not read {}: {}", path.display(), error)),
+        };
+        for (number, line) in contents.lines().enumerate() {
+            let mut parts = line.splitn(2, ' ');
+            match (parts.next().map(str::parse), parts.next()) {
+                (Some(Ok(minute)), Some(source)) => {
+                    runs.insert(source.to_owned(), minute);
+                }
+                _ => {
+                    return Err(format_err!(
+                        "{}:{}: expected a minute and an entry",
+                        path.display(),
+                        number + 1
+                    ))
+                }
+            }
+        }
+        Ok(LastRuns {
+            path: path.to_owned(),
+            runs,
+        })
+    }
+
+    pub fn get(&self, entry: &Entry) -> Option<Minute> {
+        self.runs.get(entry.source()).cloned()
+    }
+
+    /// Remember that `entry` was due at `minute`, and save the store.
+    pub fn record(&mut self, entry: &Entry, minute: Minute) -> Result<(), Error> {
+        self.runs.insert(entry.source().to_owned(), minute);
+        self.save()
+            .map_err(|error| format_err!("could not write {}: {}", self.path.display(), error))
+    }
+
+    fn save(&self) -> io::Result<()> {
+        let mut sources: Vec<&String> = self.runs.keys().collect();
+        sources.sort();
+        let temporary = self.path.with_extension("new");
+        let mut file = fs::File::create(&temporary)?;
+        for source in sources {
+            writeln!(file, "{} {}", self.runs[source], source)?;
+        }
+        file.sync_all()?;
+        fs::rename(&temporary, &self.path)
+    }
+
+    /*
+     * The runs to make up for between the last one on record and `now`:
+     * the most recent CRONDY_CATCHUP of the minutes `entry` was due in that
+     * time, oldest first, looking only as far back as CRONDY_CATCHUP_WINDOW.
+     * An entry with no run on record has missed nothing.
+     */
+    pub fn missed(&self, entry: &Entry, now: Minute) -> Vec<Minute> {
+        let options = entry.options();
+        let mut missed = VecDeque::new();
+        let last = match self.get(entry) {
+            Some(last) if options.catch_up > 0 => last,
+            _ => return Vec::new(),
+        };
+        let window_start = now - (options.catch_up_window.as_secs() / 60) as Minute;
+        let mut after = last.max(window_start - 1);
+        while let Some(next) = entry.schedule().next_after(after).filter(|&next| next <= now) {
+            if missed.len() == options.catch_up as usize {
+                missed.pop_front();
+            }
+            missed.push_back(next);
+            after = next;
+        }
+        missed.into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_files::temporary;
+    use crondy::crontab::Crontab;
+
+    const DAY: Minute = 24 * 60;
+
+    fn store(name: &str) -> PathBuf {
+        temporary(&format!("state-{}", name))
+    }
+
+    #[test]
+    fn missing_store_is_empty() {
+        let path = store("empty");
+        let tab = Crontab::parse("CRONDY_CATCHUP=1\n@daily backup").unwrap();
+        let runs = LastRuns::load(&path).unwrap();
+        assert_eq!(runs.get(&tab.entries()[0]), None);
+        assert_eq!(runs.missed(&tab.entries()[0], 10 * DAY), vec![]);
+    }
+
+    #[test]
+    fn records_and_reloads() {
+        let path = store("reload");
+        let tab = Crontab::parse("@daily backup\n@hourly  rotate logs").unwrap();
+        let mut runs = LastRuns::load(&path).unwrap();
+        runs.record(&tab.entries()[0], 42).unwrap();
+        runs.record(&tab.entries()[1], 60).unwrap();
+        runs.record(&tab.entries()[0], 1440).unwrap();
+
+        let runs = LastRuns::load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(runs.get(&tab.entries()[0]), Some(1440));
+        assert_eq!(runs.get(&tab.entries()[1]), Some(60));
+    }
+
+    #[test]
+    fn rejects_garbage() {
+        let path = store("garbage");
+        fs::write(&path, "yesterday @daily backup\n").unwrap();
+        let result = LastRuns::load(&path);
+        fs::remove_file(&path).unwrap();
+        assert!(result.is_err());
+    }
+
+    fn missed(crontab: &str, last: Minute, now: Minute) -> usize {
+        let path = store("missed");
+        let tab = Crontab::parse(crontab).unwrap();
+        let entry = &tab.entries()[0];
+        let mut runs = LastRuns::load(&path).unwrap();
+        runs.record(entry, last).unwrap();
+        fs::remove_file(&path).unwrap();
+        runs.missed(entry, now).len()
+    }
+
+    #[test]
+    fn catches_up_once_by_default() {
+        assert_eq!(missed("CRONDY_CATCHUP=yes\n@daily backup", DAY, 4 * DAY + 3), 1);
+    }
+
+    #[test]
+    fn catches_up_on_the_latest_misses() {
+        let path = store("latest");
+        let tab = Crontab::parse("CRONDY_CATCHUP=2\n@daily backup\nCRONDY_CATCHUP=1\n@daily report")
+            .unwrap();
+        let mut runs = LastRuns::load(&path).unwrap();
+        runs.record(&tab.entries()[0], DAY).unwrap();
+        runs.record(&tab.entries()[1], DAY).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(runs.missed(&tab.entries()[0], 4 * DAY + 3), vec![3 * DAY, 4 * DAY]);
+        assert_eq!(runs.missed(&tab.entries()[1], 4 * DAY + 3), vec![4 * DAY]);
+    }
+
+    #[test]
+    fn catches_up_several_times() {
+        assert_eq!(missed("CRONDY_CATCHUP=10\n@daily backup", DAY, 4 * DAY + 3), 3);
+        assert_eq!(missed("CRONDY_CATCHUP=2\n@daily backup", DAY, 4 * DAY + 3), 2);
+    }
+
+    #[test]
+    fn nothing_missed_when_up_to_date() {
+        assert_eq!(missed("CRONDY_CATCHUP=10\n@daily backup", DAY, DAY + 600), 0);
+    }
+
+    #[test]
+    fn only_looks_back_as_far_as_the_window() {
+        assert_eq!(
+            missed(
+                "CRONDY_CATCHUP=10\nCRONDY_CATCHUP_WINDOW=2d\n@daily backup",
+                DAY,
+                10 * DAY + 3
+            ),
+            2
+        );
+    }
+
+    #[test]
+    fn does_not_catch_up_unless_asked() {
+        assert_eq!(missed("@daily backup", DAY, 4 * DAY + 3), 0);
+    }
+}
@@ -0,0 +1,11 @@
+use std::fs;
+use std::path::PathBuf;
+use std::process;
+
+/// A path in the temporary directory for a test to use, by `name` and our
+/// pid so that tests don't trip over each other or over an earlier run.
+pub fn temporary(name: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("crondy-{}-{}", name, process::id()));
+    let _ = fs::remove_file(&path);
+    path
+}
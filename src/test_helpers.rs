@@ -12,3 +12,8 @@ macro_rules! assert_parses_to_exactly {
         assert_parses_to!($parse, $to, "");
     }};
 }
+
+/// A number from 0 up to but not including `n`, for quickcheck generators.
+pub fn below<G: quickcheck::Gen>(g: &mut G, n: usize) -> usize {
+    g.next_u32() as usize % n
+}
@@ -0,0 +1,121 @@
+use std::ffi::{CStr, CString};
+use std::fs;
+use std::ptr;
+
+use failure::{bail, Error};
+
+/// The parts of a passwd entry that jobs care about.
+#[derive(Debug, Clone, PartialEq)]
+pub struct User {
+    pub name: String,
+    pub uid: u32,
+    pub home: String,
+    pub shell: String,
+}
+
+impl User {
+    /// The user crondy is running as, who owns the crontab and its jobs.
+    pub fn current() -> Result<Self, Error> {
+        Self::by_uid(unsafe { libc::getuid() })
+    }
+
+    pub fn by_uid(uid: u32) -> Result<Self, Error> {
+        Self::look_up(&format!("uid {}", uid), |pwd, buf, result| unsafe {
+            libc::getpwuid_r(uid, pwd, buf.as_mut_ptr(), buf.len(), result)
+        })
+    }
+
+    pub fn by_name(name: &str) -> Result<Self, Error> {
+        let c_name = match CString::new(name) {
+            Ok(c_name) => c_name,
+            Err(_) => bail!("no passwd entry for {:?}", name),
+        };
+        Self::look_up(&format!("{:?}", name), |pwd, buf, result| unsafe {
+            libc::getpwnam_r(c_name.as_ptr(), pwd, buf.as_mut_ptr(), buf.len(), result)
+        })
+    }
+
+    /*
+     * Whether the user has a login shell: one listed in /etc/shells, as for
+     * getusershell(3), or without that file anything but the usual nologin
+     * stand-ins. An empty shell is /bin/sh.
+     */
+    pub fn can_log_in(&self) -> bool {
+        let shell = if self.shell.is_empty() { "/bin/sh" } else { &self.shell };
+        match fs::read_to_string("/etc/shells") {
+            Ok(shells) => shells.lines().any(|line| line.trim() == shell),
+            Err(_) => !(shell.ends_with("/nologin") || shell.ends_with("/false")),
+        }
+    }
+
+    /*
+     * Calls one of the getpw*_r functions, growing the buffer for it until
+     * the entry fits.
+     */
+    fn look_up<F>(what: &str, getpw: F) -> Result<Self, Error>
+    where
+        F: Fn(&mut libc::passwd, &mut Vec<libc::c_char>, &mut *mut libc::passwd) -> libc::c_int,
+    {
+        let mut buf: Vec<libc::c_char> = vec![0; 1024];
+        loop {
+            let mut pwd: libc::passwd = unsafe { std::mem::zeroed() };
+            let mut result = ptr::null_mut();
+            let errno = getpw(&mut pwd, &mut buf, &mut result);
+            match errno {
+                0 if result.is_null() => bail!("no passwd entry for {}", what),
+                0 => unsafe {
+                    return Ok(User {
+                        name: CStr::from_ptr(pwd.pw_name).to_string_lossy().into_owned(),
+                        uid: pwd.pw_uid,
+                        home: CStr::from_ptr(pwd.pw_dir).to_string_lossy().into_owned(),
+                        shell: CStr::from_ptr(pwd.pw_shell).to_string_lossy().into_owned(),
+                    });
+                },
+                libc::ERANGE if buf.len() < 1 << 20 => {
+                    let len = buf.len() * 2;
+                    buf.resize(len, 0);
+                }
+                _ => bail!(
+                    "could not look up passwd entry for {}: {}",
+                    what,
+                    std::io::Error::from_raw_os_error(errno)
+                ),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_root() {
+        assert_eq!(User::by_uid(0).unwrap().name, "root");
+    }
+
+    #[test]
+    fn finds_current_user() {
+        assert_eq!(User::current().unwrap().uid, unsafe { libc::getuid() });
+    }
+
+    #[test]
+    fn finds_root_by_name() {
+        assert_eq!(User::by_name("root").unwrap().uid, 0);
+    }
+
+    #[test]
+    fn knows_who_can_log_in() {
+        let mut user = User::by_name("root").unwrap();
+        user.shell = "/bin/sh".to_owned();
+        assert!(user.can_log_in());
+        user.shell = "/usr/sbin/nologin".to_owned();
+        assert!(!user.can_log_in());
+    }
+
+    #[test]
+    fn unknown_user_is_an_error() {
+        assert!(User::by_name("no such user").is_err());
+        assert!(User::by_uid(u32::MAX - 7).is_err())
+    }
+}